@@ -1,23 +1,28 @@
-use std::{
-    sync::{
-        atomic::{AtomicU8, Ordering},
-        Arc,
-    },
-    thread,
-    time::{Duration, Instant},
-};
+use std::collections::{BTreeMap, BTreeSet};
 
-use audioviz::audio_capture::config::Config;
-use cpal::{traits::DeviceTrait, Device, HostId};
-use crossbeam_channel::{Receiver, Sender, TryRecvError};
-use eframe::glow::COLOR;
-use egui::{Color32, Layout, Pos2, Shape, Stroke, TextBuffer, Vec2};
-use serialport::SerialPortInfo;
+use anyhow::{anyhow, Context, Result};
+use cpal::Device;
+use crossbeam_channel::{Receiver, Sender};
+use egui::{Color32, Shape, Vec2};
+use web_time::Instant;
 
 use crate::{
-    audio::{AudioThreadControlSignal, Signal, SystemMessage},
+    audio::{
+        bands::{BandId, BandLevel},
+        clock::{self, ClockControl, ClockSource, ClockState},
+        sections::SectionState,
+        tempo::Tempo,
+        AudioControl, Signal, SystemMessage,
+    },
     config,
-    dmx::DMXControl,
+    dmx::{
+        artnet::ArtNetNode,
+        universe::{OutputConfig, UniverseId},
+        DMXControl, OutputState,
+    },
+    remote::{AudioDevice, Streamed, SystemUpdate, Update},
+    supervisor::{Worker, WorkerError, WorkerState},
+    utils,
 };
 
 // pub enum Signal {
@@ -31,12 +36,97 @@ pub enum FromFrontend {
     SelectInputDevice(Option<Device>),
 }
 
+/// Where the app gets its updates from and sends its commands to.
+pub trait Backend {
+    /// The next update, `None` if there is none right now.
+    fn try_recv(&mut self) -> Option<Update>;
+
+    fn control(&mut self, control: AudioControl) -> Result<()>;
+
+    fn select_audio_device(&mut self, device: Option<AudioDevice>) -> Result<()>;
+
+    /// The output in use is reported back as `OutputSelected`, it is a dummy if opening failed.
+    fn select_output(&mut self, universe: UniverseId, output: OutputConfig) -> Result<()>;
+
+    /// Shown in the menu bar, e.g. the state of a connection.
+    fn status_ui(&mut self, _ui: &mut egui::Ui) {}
+}
+
+/// The analysis runs in this process, connected by channels.
+pub struct LocalBackend {
+    from_frontend: Sender<FromFrontend>,
+    audio_control: Sender<AudioControl>,
+    dmx_control: Sender<DMXControl>,
+    signal_in: Receiver<Signal>,
+    sys_out: Receiver<SystemMessage>,
+}
+
+impl LocalBackend {
+    pub fn new(
+        from_frontend: Sender<FromFrontend>,
+        audio_control: Sender<AudioControl>,
+        dmx_control: Sender<DMXControl>,
+        signal_in: Receiver<Signal>,
+        sys_out: Receiver<SystemMessage>,
+    ) -> Self {
+        Self {
+            from_frontend,
+            audio_control,
+            dmx_control,
+            signal_in,
+            sys_out,
+        }
+    }
+}
+
+impl Backend for LocalBackend {
+    fn try_recv(&mut self) -> Option<Update> {
+        if let Ok(signal) = self.signal_in.try_recv() {
+            return Some(Update::Signal(signal));
+        }
+        self.sys_out.try_iter().find_map(|message| message.update())
+    }
+
+    fn control(&mut self, control: AudioControl) -> Result<()> {
+        self.audio_control
+            .send(control)
+            .map_err(|_| anyhow!("The audio thread is gone"))
+    }
+
+    /// The audio thread stops the running analysis before it switches.
+    fn select_audio_device(&mut self, device: Option<AudioDevice>) -> Result<()> {
+        let device = device
+            .map(|device| {
+                utils::device_from_names(device.host, device.name.clone())
+                    .with_context(|| format!("There is no audio device `{}`", device.name))
+            })
+            .transpose()?;
+
+        self.from_frontend
+            .send(FromFrontend::SelectInputDevice(device))
+            .map_err(|_| anyhow!("The audio thread is gone"))
+    }
+
+    fn select_output(&mut self, universe: UniverseId, output: OutputConfig) -> Result<()> {
+        self.dmx_control
+            .send(DMXControl::ChangePort(universe, output))
+            .map_err(|_| anyhow!("The DMX thread is gone"))
+    }
+}
+
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct BlaulichtApp {
     log: Vec<String>,
 
+    /// Shown above the log until they are dismissed.
+    #[serde(skip)]
+    errors: Vec<WorkerError>,
+
+    #[serde(skip)]
+    workers: BTreeMap<Worker, WorkerState>,
+
     // Example stuff:
     label: String,
 
@@ -53,57 +143,92 @@ pub struct BlaulichtApp {
     beat_algo_time: Instant,
 
     #[serde(skip)]
-    signal_in: Receiver<Signal>,
+    tempo: Tempo,
 
     #[serde(skip)]
-    sys_out: Receiver<SystemMessage>,
+    bands: BTreeMap<BandId, BandLevel>,
+
+    #[serde(skip)]
+    section: SectionState,
+
+    //
+    // Beat clock.
+    //
+    #[serde(skip)]
+    clock: ClockState,
+
+    #[serde(skip)]
+    downbeat: bool,
+
+    #[serde(skip)]
+    manual_bpm: f32,
+
+    #[serde(skip)]
+    backend: Box<dyn Backend>,
 
     //
     // Audio.
     //
     #[serde(skip)]
-    audio_devices: Vec<(HostId, Device)>,
+    audio_devices: Vec<AudioDevice>,
 
     #[serde(skip)]
-    selected_audio_device: Option<Device>,
+    selected_audio_device: Option<String>,
 
     //
     // Serial.
     //
     #[serde(skip)]
-    serial_devices: Vec<SerialPortInfo>,
+    serial_devices: Vec<String>,
 
+    //
+    // Art-Net.
+    //
     #[serde(skip)]
-    selected_serial_device: Option<SerialPortInfo>,
+    artnet_nodes: Vec<ArtNetNode>,
 
-    // #[serde(skip)]
-    // dmx_control_sender: Sender<DMXControl>,
+    //
+    // DMX universes.
+    //
     #[serde(skip)]
-    config: config::Config,
+    universes: Vec<(UniverseId, OutputConfig)>,
 
+    /// Universes whose output fails, e.g. an unplugged interface.
     #[serde(skip)]
-    to_audio: Sender<FromFrontend>,
+    disconnected: BTreeSet<UniverseId>,
 
     #[serde(skip)]
-    audio_thread_control_signal: Arc<AtomicU8>,
+    config: config::Config,
 }
 
 impl Default for BlaulichtApp {
     fn default() -> Self {
         let (_, receiver) = crossbeam_channel::unbounded();
         let (sender, _) = crossbeam_channel::unbounded();
+        let (audio_control, _) = crossbeam_channel::unbounded();
+        let (dmx_control, _) = crossbeam_channel::unbounded();
         let (_, recv_sys) = crossbeam_channel::unbounded();
-        let mut audio_thread_control_signal = Arc::new(AtomicU8::new(0));
+        let backend = LocalBackend::new(sender, audio_control, dmx_control, receiver, recv_sys);
 
         Self {
             log: vec![],
+            errors: vec![],
+            workers: BTreeMap::new(),
             // Example stuff:
             label: "Hello World!".to_owned(),
             value: 2.7,
             beat: false,
             beat_algo: false,
             beat_algo_time: Instant::now(),
-            signal_in: receiver,
+            tempo: Tempo::default(),
+            bands: BTreeMap::new(),
+            section: SectionState::default(),
+            backend: Box::new(backend),
+
+            // Beat clock.
+            clock: ClockState::default(),
+            downbeat: false,
+            manual_bpm: 120.0,
 
             // Audio.
             audio_devices: vec![],
@@ -111,27 +236,26 @@ impl Default for BlaulichtApp {
 
             // Serial
             serial_devices: vec![],
-            selected_serial_device: None,
-            // dmx_control_sender: sender,
+
+            // Art-Net
+            artnet_nodes: vec![],
+
+            // Universes
+            universes: vec![],
+            disconnected: BTreeSet::new(),
 
             // Config
             config: config::Config::default(),
-            to_audio: sender,
-            audio_thread_control_signal,
-
-            sys_out: recv_sys,
         }
     }
 }
 
 impl BlaulichtApp {
     /// Called once before the first frame.
+    /// The web build connects to a running instance, its config is replaced once it arrives.
     pub fn new(
-        cc: &eframe::CreationContext<'_>,
-        from_frontend: Sender<FromFrontend>,
-        audio_thread_control_signal: Arc<AtomicU8>,
-        signal_in: Receiver<Signal>,
-        sys_recv: Receiver<SystemMessage>,
+        _cc: &eframe::CreationContext<'_>,
+        backend: Box<dyn Backend>,
         config: config::Config,
     ) -> Self {
         // This is also where you can customize the look and feel of egui using
@@ -146,47 +270,218 @@ impl BlaulichtApp {
 
         Self {
             log: vec![],
+            errors: vec![],
+            workers: BTreeMap::new(),
             label: "foo label".into(),
             value: 0f32,
             beat: false,
             beat_algo: false,
             beat_algo_time: Instant::now(),
-            signal_in,
+            tempo: Tempo::default(),
+            bands: BTreeMap::new(),
+            section: SectionState::default(),
+            backend,
+
+            clock: ClockState::default(),
+            downbeat: false,
+            manual_bpm: 120.0,
 
             audio_devices: vec![],
             selected_audio_device: None,
 
             serial_devices: vec![],
-            selected_serial_device: None,
-            to_audio: from_frontend,
-            // dmx_control_sender,
-            sys_out: sys_recv,
-            audio_thread_control_signal,
+
+            artnet_nodes: vec![],
+
+            universes: config
+                .universes
+                .iter()
+                .map(|universe| (universe.id, universe.output.clone()))
+                .collect(),
+            disconnected: BTreeSet::new(),
+
             config,
         }
     }
 
-    fn select_audio_device(&mut self, device: Option<Device>) {
-        if self.selected_audio_device.is_some() {
-            while self.audio_thread_control_signal.load(Ordering::Relaxed)
-                != AudioThreadControlSignal::DEAD
+    fn control(&mut self, control: AudioControl) {
+        if let Err(err) = self.backend.control(control) {
+            self.log.push(format!("{err:#}"));
+        }
+    }
+
+    fn control_clock(&mut self, control: ClockControl) {
+        self.control(AudioControl::Clock(control));
+    }
+
+    fn set_config(&mut self, config: config::Config) {
+        self.universes = config
+            .universes
+            .iter()
+            .map(|universe| (universe.id, universe.output.clone()))
+            .collect();
+        self.config = config;
+    }
+
+    fn handle(&mut self, update: Update) {
+        match update {
+            Update::Config(config) => self.set_config(*config),
+            Update::Signal(signal) => match signal {
+                Signal::Volume(new_vol) => {
+                    self.value = new_vol;
+                }
+                Signal::Bass(_) => {}
+                Signal::BeatVolume(v) => {
+                    self.beat = v > 0.0;
+                }
+                Signal::BeatAlgo(_) => {}
+                Signal::Tempo(tempo) => self.tempo = tempo,
+                Signal::Beat(beat) => {
+                    self.beat_algo = true;
+                    self.beat_algo_time = Instant::now();
+                    self.downbeat = beat == 0;
+                }
+                Signal::Bar(_) => {}
+                Signal::Clock(clock) => self.clock = clock,
+                Signal::Band {
+                    id,
+                    energy,
+                    peak,
+                    onset,
+                } => {
+                    let level = self.bands.entry(id).or_default();
+                    level.energy = energy;
+                    level.peak = peak;
+                    level.onset |= onset;
+                }
+                Signal::Section(section) => self.section = section,
+                Signal::SectionChange(_) => {}
+            },
+            Update::System(update) => match update {
+                SystemUpdate::Log { message } => self.log.push(message),
+                SystemUpdate::Error(error) => self.errors.push(error),
+                SystemUpdate::Worker { worker, state } => {
+                    self.workers.insert(worker, state);
+                }
+                SystemUpdate::AudioDevices { devices } => self.audio_devices = devices,
+                SystemUpdate::AudioSelected { device } => self.selected_audio_device = device,
+                SystemUpdate::SerialDevices { ports } => self.serial_devices = ports,
+                SystemUpdate::ArtNetNodes { nodes } => self.artnet_nodes = nodes,
+                SystemUpdate::OutputSelected { universe, output } => {
+                    self.select_output(universe, output)
+                }
+                SystemUpdate::OutputState { universe, state } => match state {
+                    OutputState::Connected => {
+                        self.disconnected.remove(&universe);
+                    }
+                    OutputState::Disconnected => {
+                        self.disconnected.insert(universe);
+                    }
+                },
+                SystemUpdate::AgcCalibrated { gain } => {
+                    self.config.agc.calibrated_gain = Some(gain);
+                    self.log
+                        .push(format!("Room calibrated, the gain is {gain}"));
+                }
+            },
+        }
+    }
+
+    fn beat_clock_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "Clock: {:.1} BPM, beat {}/{} ({:?})",
+            self.clock.bpm,
+            self.clock.beat + 1,
+            self.clock.beats_per_bar,
+            self.clock.source
+        ));
+
+        ui.horizontal(|ui| {
+            if ui.button("Tap").clicked() {
+                self.control_clock(ClockControl::Tap);
+            }
+            if ui.button("<<").on_hover_text("Nudge back").clicked() {
+                self.control_clock(ClockControl::NudgeBack);
+            }
+            if ui.button(">>").on_hover_text("Nudge forward").clicked() {
+                self.control_clock(ClockControl::NudgeForward);
+            }
+            if ui
+                .button("Resync")
+                .on_hover_text("Now is the downbeat")
+                .clicked()
             {
-                self.audio_thread_control_signal
-                    .store(AudioThreadControlSignal::ABORT, Ordering::Relaxed);
+                self.control_clock(ClockControl::Resync);
+            }
+        });
 
-                println!("Waiting for audio thread to die...");
-                thread::sleep(Duration::from_millis(100));
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.manual_bpm)
+                    .range(clock::MIN_BPM..=clock::MAX_BPM)
+                    .speed(0.1)
+                    .suffix(" BPM"),
+            );
+            if ui.button("Manual").clicked() {
+                self.control_clock(ClockControl::Manual(self.manual_bpm));
+            }
+            if ui
+                .selectable_label(self.clock.source == ClockSource::Tracker, "Follow audio")
+                .clicked()
+            {
+                self.control_clock(ClockControl::Source(ClockSource::Tracker));
             }
+        });
 
-            self.audio_thread_control_signal
-                .store(AudioThreadControlSignal::CONTINUE, Ordering::Relaxed);
+        // Keyboard: space taps, arrows nudge, enter resyncs.
+        if !ui.ctx().wants_keyboard_input() {
+            let keys = [
+                (egui::Key::Space, ClockControl::Tap),
+                (egui::Key::ArrowLeft, ClockControl::NudgeBack),
+                (egui::Key::ArrowRight, ClockControl::NudgeForward),
+                (egui::Key::Enter, ClockControl::Resync),
+            ];
+            for (key, control) in keys {
+                if ui.input(|i| i.key_pressed(key)) {
+                    self.control_clock(control);
+                }
+            }
         }
+    }
 
-        println!("Audio thread dead.");
+    fn bands_ui(&mut self, ui: &mut egui::Ui) {
+        for (id, band) in self.config.bands.iter().enumerate() {
+            let Some(level) = self.bands.get_mut(&(id as BandId)) else {
+                continue;
+            };
+
+            ui.monospace(format!(
+                "{:<8} {:>7.2} peak {:>7.2} {}",
+                band.name,
+                level.energy,
+                level.peak,
+                if level.onset { "*" } else { " " }
+            ));
+            level.onset = false;
+        }
+    }
 
-        self.to_audio
-            .send(FromFrontend::SelectInputDevice(device))
-            .unwrap();
+    /// Updates the view once the DMX thread reports the output in use.
+    fn select_output(&mut self, universe: UniverseId, output: OutputConfig) {
+        self.disconnected.remove(&universe);
+        match self.universes.iter_mut().find(|(id, _)| *id == universe) {
+            Some((_, current)) => *current = output,
+            None => {
+                self.universes.push((universe, output));
+                self.universes.sort_by_key(|(id, _)| *id);
+            }
+        }
+    }
+
+    fn select_audio_device(&mut self, device: Option<AudioDevice>) {
+        if let Err(err) = self.backend.select_audio_device(device) {
+            self.log.push(format!("{err:#}"));
+        }
 
         // todo: abort audio loop
 
@@ -221,67 +516,84 @@ impl eframe::App for BlaulichtApp {
                 }
 
                 egui::widgets::global_theme_preference_buttons(ui);
+                ui.add_space(16.0);
+                self.backend.status_ui(ui);
             });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Blaulicht");
 
+            // Restarting workers, the lights keep going with what still works.
+            for (worker, state) in &self.workers {
+                if let WorkerState::Failed {
+                    error,
+                    restarts,
+                    retry_in,
+                } = state
+                {
+                    let message = format!(
+                        "The {worker} worker failed {restarts}x, retrying in {retry_in:?}: {error}"
+                    );
+                    ui.colored_label(Color32::RED, message);
+                }
+            }
+
+            for error in self.errors.iter() {
+                ui.colored_label(Color32::RED, error.to_string());
+            }
+            if !self.errors.is_empty() && ui.button("Dismiss errors").clicked() {
+                self.errors.clear();
+            }
+
             for msg in self.log.iter() {
                 ui.monospace(msg);
             }
 
-            {
-                let button_title = format!(
-                    "Select Serial Device: {}",
-                    self.selected_serial_device
-                        .as_ref()
-                        .map_or_else(|| "NONE".to_string(), |dev| { dev.port_name.to_string() })
-                );
+            for (universe, output) in self.universes.clone() {
+                let button_title = if self.disconnected.contains(&universe) {
+                    format!("Universe {universe}: {output} (disconnected)")
+                } else {
+                    format!("Universe {universe}: {output}")
+                };
 
                 ui.menu_button(button_title, |ui| {
-                    let all_devices = self.serial_devices.clone().into_iter().chain(
-                        self.config
-                            .extra_serial_paths
-                            .iter()
-                            .map(|dev| SerialPortInfo {
-                                port_name: dev.to_string_lossy().into(),
-                                port_type: serialport::SerialPortType::Unknown,
-                            }),
-                    );
-
-                    for dev in all_devices {
-                        if ui.button(dev.port_name.clone()).clicked() {
-                            // ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-
-                            self.selected_serial_device = Some(dev.clone());
-
-                            // TODO: notify DMX thread...
-                            //
-                            //
-                            println!("update");
-
-                            // self.dmx_control_sender
-                            //     .send(DMXControl::ChangePort(Some(dev)))
-                            //     .unwrap();
-
+                    let all_outputs = self
+                        .serial_devices
+                        .iter()
+                        .cloned()
+                        .chain(
+                            self.config
+                                .extra_serial_paths
+                                .iter()
+                                .map(|dev| dev.to_string_lossy().into()),
+                        )
+                        .map(|port| OutputConfig::Serial {
+                            port,
+                            driver: None,
+                            usb: None,
+                        })
+                        .chain(self.artnet_nodes.iter().cloned().map(OutputConfig::ArtNet))
+                        .chain(
+                            self.config
+                                .sacn_universes
+                                .iter()
+                                .cloned()
+                                .map(OutputConfig::Sacn),
+                        )
+                        .chain([OutputConfig::Dummy])
+                        .collect::<Vec<_>>();
+
+                    for new_output in all_outputs {
+                        if ui.button(new_output.to_string()).clicked() {
+                            if let Err(err) = self.backend.select_output(universe, new_output) {
+                                self.log.push(format!("{err:#}"));
+                            }
+
+                            ui.close_menu();
                             ctx.request_repaint();
                         }
                     }
-
-                    if ui.button("NONE").clicked() {
-                        self.selected_serial_device = None;
-
-                        // TODO: notify DMX thread...
-                        //
-                        println!("update none");
-
-                        // self.dmx_control_sender
-                        //     .send(DMXControl::ChangePort(None))
-                        //     .unwrap();
-
-                        ctx.request_repaint();
-                    }
                 });
             }
 
@@ -292,18 +604,13 @@ impl eframe::App for BlaulichtApp {
             {
                 let button_title = format!(
                     "Select Audio Device: {}",
-                    self.selected_audio_device
-                        .as_ref()
-                        .map_or_else(|| "NONE".to_string(), |dev| { dev.name().unwrap() })
+                    self.selected_audio_device.as_deref().unwrap_or("NONE")
                 );
 
                 ui.menu_button(button_title, |ui| {
                     // TODO: this is horrible, it is soo slow.
                     for dev in self.audio_devices.clone().into_iter() {
-                        if ui
-                            .button(format!("{}:{}", dev.0.name(), dev.1.name().unwrap()))
-                            .clicked()
-                        {
+                        if ui.button(format!("{}:{}", dev.host, dev.name)).clicked() {
                             // ctx.send_viewport_cmd(egui::ViewportCommand::Close);
 
                             // self.selected_audio_device = Some(dev.1.clone());
@@ -316,11 +623,7 @@ impl eframe::App for BlaulichtApp {
                             //     .send(FromFrontend::SelectInputDevice(Some(dev.1.clone())))
                             //     .unwrap();
                             //
-                            self.select_audio_device(Some(dev.1.clone()));
-
-                            // self.dmx_control_sender
-                            //     .send(DMXControl::ChangePort(Some(dev)))
-                            //     .unwrap();
+                            self.select_audio_device(Some(dev));
 
                             ctx.request_repaint();
                         }
@@ -329,13 +632,6 @@ impl eframe::App for BlaulichtApp {
                     if ui.button("NONE").clicked() {
                         // self.selected_audio_device = None;
 
-                        // TODO: notify DMX thread...
-                        //
-                        // println!("update none");
-                        //
-                        // // self.dmx_control_sender
-                        // //     .send(DMXControl::ChangePort(None))
-                        // //     .unwrap();
                         // self.to_audio
                         //     .send(FromFrontend::SelectInputDevice(None))
                         //     .unwrap();
@@ -348,43 +644,26 @@ impl eframe::App for BlaulichtApp {
                 });
             }
 
-            if let Ok(sig) = self.signal_in.try_recv() {
-                if self.beat_algo_time.elapsed().as_millis() > 10 {
-                    self.beat_algo = false;
-                }
-
-                match sig {
-                    Signal::Volume(new_vol) => {
-                        self.value = new_vol as f32;
-                    }
-                    Signal::Bass(_) => {}
-                    Signal::BeatVolume(v) => {
-                        self.beat = v > 0;
-                    }
-                    Signal::BeatAlgo(v) => {
-                        self.beat_algo = true;
-                        self.beat_algo_time = Instant::now();
-                    }
-                }
+            if self.beat_algo_time.elapsed().as_millis() > 10 {
+                self.beat_algo = false;
             }
 
-            match self.sys_out.try_recv() {
-                Ok(SystemMessage::Log(msg)) => self.log.push(msg),
-                Ok(SystemMessage::LoopSpeed(speed)) => {}
-                Ok(SystemMessage::AudioDevicesView(devices)) => {
-                    self.audio_devices = devices;
-                }
-                Ok(SystemMessage::AudioSelected(dev)) => {
-                    self.selected_audio_device = dev;
-                }
-                Ok(SystemMessage::SerialDevicesView(devices)) => self.serial_devices = devices,
-                Ok(SystemMessage::SerialSelected(dev)) => self.selected_serial_device = dev,
-                Err(TryRecvError::Empty) => {}
-                Err(TryRecvError::Disconnected) => panic!("a"),
+            // Several signals are produced per frame, handle all of them.
+            while let Some(update) = self.backend.try_recv() {
+                self.handle(update);
             }
 
             ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
-                ui.add(egui::Slider::new(&mut self.value, 0.0..=255.0).text("volume"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::Slider::new(&mut self.value, 0.0..=1.0).text("volume"));
+                    if ui
+                        .button("Learn room")
+                        .on_hover_text("Calibrate the gain to the room")
+                        .clicked()
+                    {
+                        self.control(AudioControl::LearnRoom);
+                    }
+                });
 
                 {
                     let color = if self.beat {
@@ -394,7 +673,7 @@ impl eframe::App for BlaulichtApp {
                     };
 
                     let radius = 10.0;
-                    let (rect, response) =
+                    let (rect, _) =
                         ui.allocate_exact_size(Vec2::splat(radius * 2f32), egui::Sense::hover());
                     if ui.is_rect_visible(rect) {
                         let center = rect.center();
@@ -404,14 +683,16 @@ impl eframe::App for BlaulichtApp {
                 }
 
                 {
-                    let color = if self.beat_algo {
+                    let color = if self.beat_algo && self.downbeat {
+                        Color32::RED
+                    } else if self.beat_algo {
                         Color32::GREEN
                     } else {
                         Color32::BLACK
                     };
 
                     let radius = 10.0;
-                    let (rect, response) =
+                    let (rect, _) =
                         ui.allocate_exact_size(Vec2::splat(radius * 2f32), egui::Sense::hover());
                     if ui.is_rect_visible(rect) {
                         let center = rect.center();
@@ -419,6 +700,23 @@ impl eframe::App for BlaulichtApp {
                         ui.painter().add(circle);
                     }
                 }
+
+                if self.tempo.bpm > 0.0 {
+                    ui.label(format!(
+                        "{:.1} BPM ({:.0}% confidence)",
+                        self.tempo.bpm,
+                        self.tempo.confidence * 100.0
+                    ));
+                }
+
+                ui.label(format!(
+                    "Section: {} ({:.0}% confidence)",
+                    self.section.section,
+                    self.section.confidence * 100.0
+                ));
+
+                self.beat_clock_ui(ui);
+                self.bands_ui(ui);
             });
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
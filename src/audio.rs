@@ -1,17 +1,15 @@
 use std::{
-    borrow::Cow,
-    collections::{vec_deque, VecDeque},
-    net::UdpSocket,
+    fmt,
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
     },
     thread,
-    time::{self, Duration, Instant},
+    time::{Duration, Instant},
     u8,
 };
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use audioviz::audio_capture::{capture::Capture, config::Config as CaptureConfig};
 use audioviz::{
     audio_capture::capture::CaptureReceiver,
@@ -21,24 +19,63 @@ use audioviz::{
     },
 };
 use beat_detector::recording;
-use cpal::{traits::DeviceTrait, BufferSize, Device, HostId};
-use crossbeam_channel::Sender;
-use log::{debug, info, warn};
-use serialport::{SerialPortInfo, SerialPortType};
+use cpal::{traits::DeviceTrait, Device, HostId};
+use crossbeam_channel::{Receiver, Sender};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serialport::SerialPortInfo;
+
+pub mod agc;
+pub mod analysis;
+pub mod bands;
+pub mod clock;
+pub mod file;
+pub mod sections;
+pub mod tempo;
+
+use analysis::Analyzer;
+use bands::BandId;
+use clock::{ClockControl, ClockState};
+use file::{FileInput, FileSource, Playback};
+use sections::{SectionChange, SectionState};
+use tempo::Tempo;
 
 use crate::{
-    dmx::{DmxUniverse, USB_DEVICES},
-    utils::{self},
+    config as app_config,
+    dmx::{
+        artnet::ArtNetNode,
+        universe::{DmxFrames, OutputConfig, UniverseId},
+        OutputState,
+    },
+    effect::{EffectControl, EffectEngine},
+    events::{Event, EventPublisher},
+    fixture::patch::Patch,
+    supervisor::{Worker, WorkerError, WorkerState},
 };
 
-fn map(x: isize, in_min: isize, in_max: isize, out_min: isize, out_max: isize) -> usize {
-    let divisor = (in_max - in_min).max(1);
-    ((x - in_min) * (out_max - out_min) / (divisor) + out_min).max(0) as usize
-}
-
 pub enum ConverterType {
     Stream(Stream),
     Capture(Capture),
+    /// Boxed, a file source is much larger than the others.
+    File(Box<FileSource>),
+}
+
+/// Where the analyzed audio comes from.
+#[derive(Clone)]
+pub enum AudioSource {
+    Device(Device),
+    File(FileInput),
+}
+
+impl fmt::Display for AudioSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioSource::Device(device) => {
+                write!(f, "{}", device.name().unwrap_or_else(|_| "?".into()))
+            }
+            AudioSource::File(input) => write!(f, "file `{input}`"),
+        }
+    }
 }
 
 pub struct Converter {
@@ -86,9 +123,11 @@ impl Default for Config {
 }
 
 impl Converter {
-    pub fn from_capture(capture: Capture, config: Config) -> Self {
-        let raw_receiver = capture.get_receiver().unwrap();
-        Self {
+    pub fn from_capture(capture: Capture, config: Config) -> anyhow::Result<Self> {
+        let raw_receiver = capture
+            .get_receiver()
+            .map_err(|err| anyhow!("Failed to receive from the capture: {err:?}"))?;
+        Ok(Self {
             conv_type: ConverterType::Capture(capture),
             raw_buf: Vec::new(),
             show_vec: Vec::new(),
@@ -96,7 +135,7 @@ impl Converter {
             stream_controller: None,
             config,
             resolution: 0,
-        }
+        })
     }
 
     pub fn from_stream(stream: Stream, config: Config) -> Self {
@@ -112,6 +151,34 @@ impl Converter {
         }
     }
 
+    pub fn from_file(file: FileSource, config: Config) -> Self {
+        Self {
+            conv_type: ConverterType::File(Box::new(file)),
+            raw_buf: Vec::new(),
+            show_vec: Vec::new(),
+            raw_receiver: None,
+            stream_controller: None,
+            config,
+            resolution: 0,
+        }
+    }
+
+    /// Timestamp of the next spectrum, files are analyzed on their own timeline.
+    pub fn now(&self) -> Instant {
+        match &self.conv_type {
+            ConverterType::File(file) => file.now(),
+            _ => Instant::now(),
+        }
+    }
+
+    /// Whether the input arrives in real time, i.e. idle loops should sleep.
+    pub fn is_realtime(&self) -> bool {
+        match &self.conv_type {
+            ConverterType::File(file) => file.playback() == Playback::Realtime,
+            _ => true,
+        }
+    }
+
     pub fn get_data(&mut self) -> Option<Vec<f32>> {
         if let Some(raw) = &self.raw_receiver {
             let mut data: Vec<f32> = match raw.receive_data() {
@@ -150,75 +217,92 @@ impl Converter {
         None
     }
 
-    pub fn freqs(&mut self) -> Vec<Frequency> {
+    /// Returns `None` once the input has ended.
+    pub fn freqs(&mut self) -> Option<Vec<Frequency>> {
+        if let ConverterType::File(file) = &mut self.conv_type {
+            return file.next_spectrum();
+        }
+
         if let Some(stream) = &self.stream_controller {
             let freqs = stream.get_frequencies();
-            return freqs;
+            return Some(freqs);
         }
 
-        panic!("broken");
+        // A capture without a stream has no spectrum, there is nothing to analyze.
+        None
     }
 }
 
-#[derive(Clone, Copy)]
+/// Analysis results, all levels are normalized to `0.0..=1.0`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Signal {
-    BeatVolume(u8),
-    BeatAlgo(u8),
-    Bass(u8),
-    Volume(u8),
+    BeatVolume(f32),
+    /// A beat of the tracked tempo, the value is the tracker's confidence.
+    BeatAlgo(f32),
+    /// The loudest bin of the bass range, 0 during a drop.
+    Bass(f32),
+    Volume(f32),
+    Tempo(Tempo),
+    /// A beat of the beat clock, the value is the beat inside the bar (0 is the downbeat).
+    Beat(u8),
+    /// The downbeat of a bar, the value is the bar number (wrapping).
+    Bar(u8),
+    Clock(ClockState),
+    /// Level of a configured frequency band.
+    Band {
+        id: BandId,
+        energy: f32,
+        peak: f32,
+        onset: bool,
+    },
+    /// The current song section, published periodically.
+    Section(SectionState),
+    /// The song entered a new section.
+    SectionChange(SectionChange),
 }
 
 pub enum SystemMessage {
     Log(String),
+    Error(WorkerError),
+    Worker(Worker, WorkerState),
     LoopSpeed(Duration),
     // Audio.
     AudioSelected(Option<Device>),
     AudioDevicesView(Vec<(HostId, Device)>),
+    // DMX outputs.
+    OutputSelected(UniverseId, OutputConfig),
+    OutputState(UniverseId, OutputState),
     // Serial.
-    SerialSelected(Option<SerialPortInfo>),
     SerialDevicesView(Vec<SerialPortInfo>),
+    // Art-Net.
+    ArtNetNodesView(Vec<ArtNetNode>),
+    // AGC.
+    AgcCalibrated(f32),
 }
 
-const ROLLING_AVERAGE_LOOP_ITERATIONS: usize = 100;
-const ROLLING_AVERAGE_VOLUME_SAMPLE_SIZE: usize = ROLLING_AVERAGE_LOOP_ITERATIONS / 2;
+/// Commands for the analysis thread, e.g. from the GUI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioControl {
+    Clock(ClockControl),
+    /// Listens to the room for a while and derives the AGC gain from it.
+    LearnRoom,
+    /// Handled by the effect engine, which runs in the analysis thread.
+    Effects(EffectControl),
+}
 
 const SYSTEM_MESSAGE_SPEED: Duration = Duration::from_millis(1000);
-const SIGNAL_SPEED: Duration = Duration::from_millis(10);
 
 macro_rules! system_message {
     ($now:ident,$last_publish:ident,$system_out:ident,$message:expr) => {
         if $now - $last_publish > SYSTEM_MESSAGE_SPEED {
-            $system_out.send($message).unwrap();
+            $system_out.send($message).ok();
             $last_publish = $now
         }
     };
 }
 
-macro_rules! signal {
-    ($now:ident,$last_publish:ident,$out0:ident,$dmx:ident,$tx_signal:expr) => {
-        if $now - $last_publish > SIGNAL_SPEED {
-            $out0.send($tx_signal).unwrap();
-            $dmx.signal($tx_signal);
-            $last_publish = $now;
-        }
-    };
-}
-
-///
-///
-/// Vector push operations.
-///
-///
-
-macro_rules! shift_push {
-    ($vector:ident,$capacity:ident,$item:expr) => {
-        $vector.push_back($item);
-        if $vector.len() > $capacity {
-            $vector.pop_front();
-        }
-    };
-}
-
 #[non_exhaustive]
 pub struct AudioThreadControlSignal;
 
@@ -228,21 +312,36 @@ impl AudioThreadControlSignal {
     pub const DEAD: u8 = 2;
 }
 
-pub fn run(
-    device: Device,
-    signal_out_0: Sender<Signal>,
-    // signal_out_1: Sender<Signal>,
-    system_out: Sender<SystemMessage>,
-    thread_control_signal: Arc<AtomicU8>,
-) -> anyhow::Result<()> {
+pub fn run(source: AudioSource, context: AnalysisContext) -> anyhow::Result<()> {
     let config = Config::default();
 
-    // TODO: select a device!
+    let device = match source {
+        AudioSource::Device(device) => device,
+        AudioSource::File(input) => {
+            let file = FileSource::open(&input, config.audio.clone())?;
+            context
+                .system_out
+                .send(SystemMessage::Log(format!(
+                    "[audio] Analyzing `{input}` ({:.0}s, {:?} playback)",
+                    file.duration().as_secs_f32(),
+                    input.playback
+                )))
+                .ok();
+            let converter = Converter::from_file(file, config.clone());
+            return analyze(converter, context);
+        }
+    };
 
     let audio_capture_config = CaptureConfig {
-        sample_rate: Some(device.default_input_config().unwrap().sample_rate().0),
+        sample_rate: Some(
+            device
+                .default_input_config()
+                .context("The audio device has no input config")?
+                .sample_rate()
+                .0,
+        ),
         latency: None,
-        device: device.name().unwrap(),
+        device: device.name().context("The audio device is gone")?,
         buffer_size: CaptureConfig::default().buffer_size,
         max_buffer_size: CaptureConfig::default().max_buffer_size,
     };
@@ -266,15 +365,49 @@ pub fn run(
     // .unwrap();
     // End beat detection
 
-    let mut converter: Converter = match config.visualisation {
+    let converter: Converter = match config.visualisation {
         Visualisation::Spectrum => {
             let stream = Stream::init_with_capture(&capture, config.audio.clone());
 
             Converter::from_stream(stream, config.clone())
         }
-        Visualisation::Scope => Converter::from_capture(capture, config.clone()),
+        Visualisation::Scope => Converter::from_capture(capture, config.clone())?,
     };
 
+    analyze(converter, context)
+}
+
+/// Everything an analysis needs besides its input: the channels to the other threads and the
+/// state it shares with them.
+#[derive(Clone)]
+pub struct AnalysisContext {
+    pub signal_out: Sender<Signal>,
+    pub system_out: Sender<SystemMessage>,
+    /// Aborts the analysis, see [`AudioThreadControlSignal`].
+    pub thread_control_signal: Arc<AtomicU8>,
+    pub audio_control: Receiver<AudioControl>,
+    pub app_config: app_config::Config,
+    pub dmx_frames: DmxFrames,
+    pub patch: Patch,
+}
+
+/// The analysis loop, runs until it is aborted or the input ends.
+fn analyze(mut converter: Converter, context: AnalysisContext) -> anyhow::Result<()> {
+    let AnalysisContext {
+        signal_out: signal_out_0,
+        system_out,
+        thread_control_signal,
+        audio_control,
+        mut app_config,
+        dmx_frames,
+        patch,
+    } = context;
+
+    // A calibration of an earlier analysis is only in the file.
+    if let Some(gain) = app_config.stored_agc_gain() {
+        app_config.agc.calibrated_gain = Some(gain);
+    }
+
     // let (signal_out, signal_receiver) = mpsc::channel();
     // let (system_out, system_receiver) = mpsc::channel();
 
@@ -296,39 +429,11 @@ pub fn run(
     //
 
     //
-    // DMX interfaces.
+    // DMX show, the output thread transmits the frame.
     //
-    let ports = serialport::available_ports()?;
-
-    // Update available ports to frontend.
-    system_out
-        .send(SystemMessage::SerialDevicesView(ports.clone()))
-        .unwrap();
-
-    println!("{ports:?}");
-
-    let serial_port = ports.into_iter().find(|p| match p.port_type.clone() {
-        SerialPortType::UsbPort(usb) => USB_DEVICES
-            .iter()
-            .any(|d| d.pid == usb.pid && d.vid == usb.vid),
-        _ => false,
-    });
-
-    if serial_port.is_none() {
-        warn!("No default DMX serial output available");
-    }
-
-    let mut dmx_universe = match serial_port {
-        Some(port) => {
-            let serial_port_name = port.port_name.clone();
-            info!("[DMX] Using serial device: {serial_port_name}");
-            system_out
-                .send(SystemMessage::SerialSelected(Some(port.clone())))
-                .unwrap();
-            DmxUniverse::new(serial_port_name)
-        }
-        None => DmxUniverse::new_dummy(),
-    };
+    let mut dmx_show = EffectEngine::new(dmx_frames, app_config.dmx_refresh_rate, patch)
+        .with_effects(&app_config.effects)
+        .with_scenes(&app_config.scenes);
 
     // let Some(port) = port.cloned() else {
     //     warn!("[DMX] No default serial device available...");
@@ -339,51 +444,27 @@ pub fn run(
 
     // let mut port = Some(port);
 
-    // Energy saving.
-    let mut loop_inactive = true;
+    let mut analyzer = Analyzer::new(&app_config, converter.now());
 
     // Loop speed.
-    let mut time_of_last_system_publish = time::Instant::now();
-    let mut loop_begin_time = time::Instant::now();
-
-    // Volume.
-    let mut time_of_last_volume_publish = time::Instant::now();
-    let mut volume_samples: VecDeque<usize> =
-        VecDeque::with_capacity(ROLLING_AVERAGE_LOOP_ITERATIONS);
-
-    // Beat
-    let mut time_of_last_beat_publish = time::Instant::now();
-    let mut last_index = 0;
-    let rolling_average_frames = 100;
-    let long_historic_frames = rolling_average_frames * 100;
-    let mut long_historic = VecDeque::with_capacity(long_historic_frames);
-    let mut historic = VecDeque::with_capacity(rolling_average_frames);
-
-    const BASS_FRAMES: usize = 800;
-    let mut bass_samples = VecDeque::with_capacity(BASS_FRAMES);
-    let mut last_bass_udp_update = Instant::now();
+    let mut time_of_last_system_publish = converter.now();
+    let mut loop_begin_time = converter.now();
 
-    //
-    //
-    // TODO: beat detection
-    //
-    //
-    //
-    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let mut events = EventPublisher::new(&app_config.event_targets)?;
 
     loop {
         //
         // Loop control.
         //
         if thread_control_signal.load(Ordering::Relaxed) == AudioThreadControlSignal::ABORT {
-            println!("Received kill, giving up...");
+            debug!("[audio] Analysis aborted");
             break Ok(());
         }
 
         //
         // Measure loop speed.
         //
-        let now = time::Instant::now();
+        let now = converter.now();
         {
             let loop_speed = now - loop_begin_time;
             loop_begin_time = now;
@@ -398,166 +479,57 @@ pub fn run(
 
         /////////////////// Signal Begin ///////////////
 
-        let values = converter.freqs();
+        let Some(raw_values) = converter.freqs() else {
+            system_out
+                .send(SystemMessage::Log("[audio] End of input".into()))
+                .ok();
+            break Ok(());
+        };
 
         //
-        // Update volume signal.
+        // Commands.
         //
-        {
-            signal!(
-                now,
-                time_of_last_volume_publish,
-                signal_out_0,
-                dmx_universe,
-                {
-                    let volume_mean = ((volume_samples.iter().sum::<usize>() as f32)
-                        / (volume_samples.len() as f32)
-                        * 10.0) as usize;
-
-                    Signal::Volume((volume_mean as u8).saturating_mul(10u8))
-                }
-            );
-
-            shift_push!(
-                volume_samples,
-                ROLLING_AVERAGE_VOLUME_SAMPLE_SIZE,
-                values
-                    .iter()
-                    .max_by_key(|f| (f.volume * 10.0) as usize)
-                    .unwrap_or(&Frequency {
-                        volume: 0f32,
-                        freq: 0f32,
-                        position: 0f32
-                    })
-                    .volume as usize
-            );
+        while let Ok(control) = audio_control.try_recv() {
+            let message = match control {
+                AudioControl::Effects(control) => dmx_show.control(control).err().map(|err| {
+                    SystemMessage::Error(WorkerError::new(Worker::Analysis, err.to_string()))
+                }),
+                control => analyzer.control(control, now),
+            };
+            if let Some(message) = message {
+                system_out.send(message).ok();
+            }
         }
 
-        //
-        // Update Bass.
-        //
+        let analysis = analyzer.process(now, &raw_values);
 
-        signal!(
-            now,
-            time_of_last_beat_publish,
-            signal_out_0,
-            dmx_universe,
-            {
-                let v = values
-                    .iter()
-                    .filter(|f| f.freq <= 150.0)
-                    .map(|f| f.volume as usize)
-                    .collect::<Vec<usize>>();
-
-                let avg = v.iter().sum::<usize>() as f32 / v.len() as f32;
-                let sig = (avg * 10.0) as u8;
-
-                bass_samples.push_back(sig);
-
-                if bass_samples.len() >= BASS_FRAMES {
-                    bass_samples.pop_front();
+        for message in analysis.messages {
+            if let SystemMessage::AgcCalibrated(gain) = message {
+                if let Err(err) = app_config.store_agc_gain(gain) {
+                    warn!("[audio] Failed to store the calibrated gain: {err:#}");
                 }
-
-                // Drop detection.
-                let len = bass_samples.len();
-                let drop = bass_samples.iter().filter(|b| **b > 20).count() >= len / 3;
-
-                if last_bass_udp_update.elapsed().as_millis() >= 1000 {
-                    last_bass_udp_update = Instant::now();
-                    println!("drop={drop}");
-                    socket
-                        .send_to(
-                            &[b'D', if drop { b'1' } else { b'0' }],
-                            "192.168.0.100:33333",
-                        )
-                        .unwrap_or_else(|err| {
-                            println!("error UDP: {:?}", err);
-                            0
-                        });
-
-                }
-
-
-                Signal::Bass(if drop { 0 } else { sig })
-            }
-        );
-
-        //
-        // Update loudest signal.
-        //
-
-        {
-            let curr: Vec<usize> = values
-                .chunks(2)
-                // TODO: only look at the base line?
-                .map(|f| f.iter().map(|e| e.volume as usize).max().unwrap())
-                .collect();
-
-            let curr = curr.iter().max().unwrap_or(&0);
-
-            let curr_unfiltered: usize = values.iter().map(|f| f.volume as usize).sum();
-
-            long_historic.push_back(curr_unfiltered);
-            if long_historic.len() >= long_historic_frames {
-                long_historic.pop_front();
-            }
-
-            historic.push_back(*curr);
-
-            if historic.len() >= rolling_average_frames {
-                historic.pop_front();
             }
+            system_out.send(message).ok();
+        }
 
-            let sum = historic.iter().sum::<usize>();
-            let avg = sum / rolling_average_frames;
-            let max = historic.iter().max().unwrap_or(&usize::MAX);
-            let min = historic.iter().min().unwrap_or(&usize::MIN);
-
-            let long_sum = long_historic.iter().sum::<usize>();
-
-            if long_sum == 0 {
-                if !loop_inactive {
-                    eprintln!("long historic is 0: sleeping");
-                }
-
-                debug!("[AUDIO] Entering sleep mode...");
-                thread::sleep(Duration::from_millis(500));
-                loop_inactive = true;
-            } else if loop_inactive {
-                eprintln!("long = {long_sum}");
-                loop_inactive = false
+        for signal in analysis.signals {
+            // Nobody listens anymore, the instance is shutting down.
+            if signal_out_0.send(signal).is_err() {
+                return Ok(());
             }
-
-            const MAX_BEAT_VOLUME: u8 = 255;
-            let index_mapped = map(
-                *curr as isize,
-                *min as isize,
-                *max as isize,
-                0,
-                MAX_BEAT_VOLUME as isize,
-            );
-
-            if last_index == index_mapped {
-                continue;
+            dmx_show.signal(signal);
+            if let Some(event) = Event::from_signal(signal) {
+                events.publish(now, &event);
             }
+        }
 
-            let now = time::Instant::now();
-
-            signal!(
-                now,
-                time_of_last_beat_publish,
-                signal_out_0,
-                dmx_universe,
-                {
-                    eprintln!(
-                "index = {index_mapped:02} | curr = {curr:03} | min = {min:03} | avg = {avg:03} | max = {max:03}",
-            );
-
-                    last_index = index_mapped;
+        if let Some(active) = analysis.drop {
+            events.publish(now, &Event::Drop { active });
+        }
 
-                    Signal::BeatVolume(index_mapped as u8)
-                }
-            );
+        // Energy saving.
+        if analysis.idle && converter.is_realtime() {
+            thread::sleep(Duration::from_millis(500));
         }
     }
 }
@@ -0,0 +1,175 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use audioviz::spectrum::Frequency;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::smoothing_factor;
+
+/// The level follower rises immediately and falls with this time constant.
+const LEVEL_RELEASE: Duration = Duration::from_secs(3);
+/// How long the room is listened to for a calibration.
+pub const LEARN_DURATION: Duration = Duration::from_secs(10);
+/// The calibration ignores the loudest moments, this quantile of the levels is used.
+const LEARN_QUANTILE: f32 = 0.9;
+
+/// Automatic gain control applied to the spectrum before any signal is computed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgcConfig {
+    /// Without AGC the calibrated (or unity) gain is used.
+    pub enabled: bool,
+    /// How quickly the gain is reduced when the input gets louder, in milliseconds.
+    pub attack_ms: u64,
+    /// How quickly the gain is raised when the input gets quieter, in milliseconds.
+    pub release_ms: u64,
+    /// Level the loudest bin is normalized to, `0.0..=1.0`.
+    pub target: f32,
+    pub min_gain: f32,
+    pub max_gain: f32,
+    /// Inputs below this (raw) level count as silence and do not raise the gain.
+    pub noise_floor: f32,
+    /// Gain found by "learn room", used as the starting point.
+    pub calibrated_gain: Option<f32>,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            attack_ms: 300,
+            release_ms: 5000,
+            target: 0.7,
+            min_gain: 0.01,
+            max_gain: 100.0,
+            noise_floor: 0.01,
+            calibrated_gain: None,
+        }
+    }
+}
+
+impl AgcConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.target > 0.0 && self.target <= 1.0) {
+            bail!("`agc.target` must be in (0, 1]");
+        }
+
+        if !(self.min_gain > 0.0 && self.min_gain <= self.max_gain) {
+            bail!("`agc.min_gain` must be positive and at most `agc.max_gain`");
+        }
+
+        Ok(())
+    }
+}
+
+struct Calibration {
+    until: Instant,
+    levels: Vec<f32>,
+}
+
+/// Normalizes spectra so that all downstream signals are in `0.0..=1.0`.
+pub struct Agc {
+    config: AgcConfig,
+    gain: f32,
+    /// Raw level of the loudest bin, with a slow release.
+    level: f32,
+    last_update: Option<Instant>,
+    calibration: Option<Calibration>,
+}
+
+impl Agc {
+    pub fn new(config: AgcConfig) -> Self {
+        let gain = config
+            .calibrated_gain
+            .unwrap_or(1.0)
+            .clamp(config.min_gain, config.max_gain);
+
+        Self {
+            config,
+            gain,
+            level: 0.0,
+            last_update: None,
+            calibration: None,
+        }
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.calibration.is_some()
+    }
+
+    /// Starts listening to the room, see [`Agc::process`] for the result.
+    pub fn learn(&mut self, now: Instant) {
+        self.calibration = Some(Calibration {
+            until: now + LEARN_DURATION,
+            levels: vec![],
+        });
+    }
+
+    /// Scales the spectrum in place.
+    /// Returns the calibrated gain when a "learn room" calibration has finished.
+    pub fn process(&mut self, now: Instant, spectrum: &mut [Frequency]) -> Option<f32> {
+        let elapsed = self
+            .last_update
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last_update = Some(now);
+
+        let peak = spectrum.iter().map(|f| f.volume).fold(0.0, f32::max);
+        self.level = if peak >= self.level {
+            peak
+        } else {
+            self.level + (peak - self.level) * smoothing_factor(elapsed, LEVEL_RELEASE)
+        };
+
+        let calibrated = self.calibrate(now, peak);
+
+        if self.config.enabled && self.level > self.config.noise_floor {
+            let wanted =
+                (self.config.target / self.level).clamp(self.config.min_gain, self.config.max_gain);
+            let time_constant = if wanted < self.gain {
+                self.config.attack_ms
+            } else {
+                self.config.release_ms
+            };
+            self.gain += (wanted - self.gain)
+                * smoothing_factor(elapsed, Duration::from_millis(time_constant));
+        }
+
+        for f in spectrum.iter_mut() {
+            f.volume = (f.volume * self.gain).clamp(0.0, 1.0);
+        }
+
+        calibrated
+    }
+
+    fn calibrate(&mut self, now: Instant, peak: f32) -> Option<f32> {
+        let calibration = self.calibration.as_mut()?;
+
+        if peak > self.config.noise_floor {
+            calibration.levels.push(peak);
+        }
+
+        if now < calibration.until {
+            return None;
+        }
+
+        let mut levels = std::mem::take(&mut calibration.levels);
+        self.calibration = None;
+
+        if levels.is_empty() {
+            return None;
+        }
+
+        levels.sort_by(f32::total_cmp);
+        let index = ((levels.len() - 1) as f32 * LEARN_QUANTILE) as usize;
+        let gain =
+            (self.config.target / levels[index]).clamp(self.config.min_gain, self.config.max_gain);
+
+        self.gain = gain;
+        self.config.calibrated_gain = Some(gain);
+        Some(gain)
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use audioviz::spectrum::Frequency;
+use log::debug;
+
+use super::{
+    agc::{self, Agc},
+    bands::BandAnalyzer,
+    clock::BeatClock,
+    sections::SectionTracker,
+    tempo::TempoTracker,
+    AudioControl, Signal, SystemMessage,
+};
+use crate::config::Config;
+
+const ROLLING_AVERAGE_LOOP_ITERATIONS: usize = 100;
+const ROLLING_AVERAGE_VOLUME_SAMPLE_SIZE: usize = ROLLING_AVERAGE_LOOP_ITERATIONS / 2;
+
+const SIGNAL_SPEED: Duration = Duration::from_millis(10);
+const TEMPO_SIGNAL_SPEED: Duration = Duration::from_millis(50);
+const BAND_SIGNAL_SPEED: Duration = Duration::from_millis(20);
+const SECTION_SIGNAL_SPEED: Duration = Duration::from_millis(250);
+/// The drop state is reported this often.
+const DROP_REPORT_SPEED: Duration = Duration::from_secs(1);
+
+/// Beats of the tempo tracker are only published if it is at least this confident.
+const MIN_BEAT_CONFIDENCE: f32 = 0.1;
+
+/// A drop is detected if the bass is above this level for a third of the recent frames.
+const DROP_BASS_LEVEL: f32 = 0.4;
+const BASS_FRAMES: usize = 800;
+/// The loudest bin up to this frequency is the bass level.
+const BASS_MAX_FREQ: f32 = 150.0;
+
+const ROLLING_AVERAGE_FRAMES: usize = 100;
+const LONG_HISTORIC_FRAMES: usize = ROLLING_AVERAGE_FRAMES * 100;
+const MAX_BEAT_VOLUME: u8 = 255;
+
+/// Everything the analysis of a single spectrum produced.
+#[derive(Default)]
+pub struct Analysis {
+    pub signals: Vec<Signal>,
+    pub messages: Vec<SystemMessage>,
+    /// Whether there currently is a drop, reported every [`DROP_REPORT_SPEED`].
+    pub drop: Option<bool>,
+    /// The input has been silent for a long time, live inputs can be polled less often.
+    pub idle: bool,
+}
+
+/// Turns spectra into signals.
+/// Time is passed in with every spectrum, which makes the analysis deterministic.
+pub struct Analyzer {
+    agc: Agc,
+    tempo_tracker: TempoTracker,
+    beat_clock: BeatClock,
+    band_analyzer: BandAnalyzer,
+    section_tracker: SectionTracker,
+
+    volume_samples: VecDeque<f32>,
+    bass_samples: VecDeque<f32>,
+    historic: VecDeque<f32>,
+    long_historic: VecDeque<f32>,
+    last_index: u8,
+    idle: bool,
+
+    last_volume_publish: Instant,
+    last_bass_publish: Instant,
+    last_beat_publish: Instant,
+    last_tempo_publish: Instant,
+    last_band_publish: Instant,
+    last_section_publish: Instant,
+    last_drop_report: Instant,
+}
+
+/// Returns true (and updates `last`) if a signal published at `last` may be published again.
+fn due(now: Instant, last: &mut Instant, speed: Duration) -> bool {
+    if now.saturating_duration_since(*last) > speed {
+        *last = now;
+        true
+    } else {
+        false
+    }
+}
+
+fn push_bounded(samples: &mut VecDeque<f32>, capacity: usize, sample: f32) {
+    samples.push_back(sample);
+    if samples.len() > capacity {
+        samples.pop_front();
+    }
+}
+
+impl Analyzer {
+    pub fn new(config: &Config, now: Instant) -> Self {
+        Self {
+            agc: Agc::new(config.agc.clone()),
+            tempo_tracker: TempoTracker::new(),
+            beat_clock: BeatClock::new(config.beats_per_bar),
+            band_analyzer: BandAnalyzer::new(&config.bands),
+            section_tracker: SectionTracker::new(),
+
+            volume_samples: VecDeque::with_capacity(ROLLING_AVERAGE_VOLUME_SAMPLE_SIZE + 1),
+            bass_samples: VecDeque::with_capacity(BASS_FRAMES),
+            historic: VecDeque::with_capacity(ROLLING_AVERAGE_FRAMES),
+            long_historic: VecDeque::with_capacity(LONG_HISTORIC_FRAMES),
+            last_index: 0,
+            idle: true,
+
+            last_volume_publish: now,
+            last_bass_publish: now,
+            last_beat_publish: now,
+            last_tempo_publish: now,
+            last_band_publish: now,
+            last_section_publish: now,
+            last_drop_report: now,
+        }
+    }
+
+    /// Applies a command, returns a message for the user if there is one.
+    pub fn control(&mut self, control: AudioControl, now: Instant) -> Option<SystemMessage> {
+        match control {
+            AudioControl::Clock(control) => {
+                self.beat_clock.control(control, now);
+                None
+            }
+            AudioControl::LearnRoom => {
+                self.agc.learn(now);
+                Some(SystemMessage::Log(format!(
+                    "[audio] Learning the room for {}s...",
+                    agc::LEARN_DURATION.as_secs()
+                )))
+            }
+            // Handled by the effect engine.
+            AudioControl::Effects(_) => None,
+        }
+    }
+
+    pub fn process(&mut self, now: Instant, raw_values: &[Frequency]) -> Analysis {
+        let mut analysis = Analysis::default();
+
+        //
+        // Normalize the input level.
+        //
+        let mut values = raw_values.to_vec();
+        if let Some(gain) = self.agc.process(now, &mut values) {
+            analysis.messages.push(SystemMessage::Log(format!(
+                "[audio] Learned the room, the gain is {gain}"
+            )));
+            analysis.messages.push(SystemMessage::AgcCalibrated(gain));
+        }
+
+        self.tempo(now, &values, &mut analysis);
+        self.bands(now, &values, &mut analysis);
+        self.sections(now, raw_values, &values, &mut analysis);
+        self.volume(now, &values, &mut analysis);
+        self.bass(now, &values, &mut analysis);
+        self.beat_volume(now, raw_values, &values, &mut analysis);
+
+        analysis
+    }
+
+    fn tempo(&mut self, now: Instant, values: &[Frequency], analysis: &mut Analysis) {
+        let spectrum: Vec<f32> = values.iter().map(|f| f.volume).collect();
+        let beat = self.tempo_tracker.process(now, &spectrum);
+        let tempo = self.tempo_tracker.tempo(now);
+        let beat = beat && tempo.confidence >= MIN_BEAT_CONFIDENCE;
+
+        if beat {
+            analysis.signals.push(Signal::BeatAlgo(tempo.confidence));
+        }
+
+        self.beat_clock.tracker(tempo, beat, now);
+
+        if let Some(clock_beat) = self.beat_clock.process(now) {
+            analysis.signals.push(Signal::Beat(clock_beat.beat));
+            if clock_beat.beat == 0 {
+                analysis.signals.push(Signal::Bar(clock_beat.bar));
+            }
+        }
+
+        if due(now, &mut self.last_tempo_publish, TEMPO_SIGNAL_SPEED) {
+            analysis.signals.push(Signal::Tempo(tempo));
+            analysis
+                .signals
+                .push(Signal::Clock(self.beat_clock.state(now)));
+        }
+    }
+
+    fn bands(&mut self, now: Instant, values: &[Frequency], analysis: &mut Analysis) {
+        self.band_analyzer.process(now, values);
+        if due(now, &mut self.last_band_publish, BAND_SIGNAL_SPEED) {
+            for (id, level) in self.band_analyzer.report() {
+                analysis.signals.push(Signal::Band {
+                    id,
+                    energy: level.energy,
+                    peak: level.peak,
+                    onset: level.onset,
+                });
+            }
+        }
+    }
+
+    fn sections(
+        &mut self,
+        now: Instant,
+        raw_values: &[Frequency],
+        values: &[Frequency],
+        analysis: &mut Analysis,
+    ) {
+        if let Some(change) = self.section_tracker.process(now, raw_values, values) {
+            debug!("[audio] Section {} -> {}", change.from, change.to);
+            analysis.signals.push(Signal::SectionChange(change));
+        }
+
+        if due(now, &mut self.last_section_publish, SECTION_SIGNAL_SPEED) {
+            analysis
+                .signals
+                .push(Signal::Section(self.section_tracker.state()));
+        }
+    }
+
+    fn volume(&mut self, now: Instant, values: &[Frequency], analysis: &mut Analysis) {
+        if due(now, &mut self.last_volume_publish, SIGNAL_SPEED) {
+            let volume_mean =
+                self.volume_samples.iter().sum::<f32>() / self.volume_samples.len().max(1) as f32;
+            analysis.signals.push(Signal::Volume(volume_mean));
+        }
+
+        push_bounded(
+            &mut self.volume_samples,
+            ROLLING_AVERAGE_VOLUME_SAMPLE_SIZE,
+            values.iter().map(|f| f.volume).fold(0.0, f32::max),
+        );
+    }
+
+    fn bass(&mut self, now: Instant, values: &[Frequency], analysis: &mut Analysis) {
+        if !due(now, &mut self.last_bass_publish, SIGNAL_SPEED) {
+            return;
+        }
+
+        let level = values
+            .iter()
+            .filter(|f| f.freq <= BASS_MAX_FREQ)
+            .map(|f| f.volume)
+            .fold(0.0, f32::max);
+
+        push_bounded(&mut self.bass_samples, BASS_FRAMES - 1, level);
+
+        // Drop detection, a short history must not count as a drop.
+        let drop = self
+            .bass_samples
+            .iter()
+            .filter(|b| **b > DROP_BASS_LEVEL)
+            .count()
+            >= BASS_FRAMES / 3;
+
+        if due(now, &mut self.last_drop_report, DROP_REPORT_SPEED) {
+            analysis.drop = Some(drop);
+        }
+
+        analysis
+            .signals
+            .push(Signal::Bass(if drop { 0.0 } else { level }));
+    }
+
+    /// Loudness relative to the recent minimum and maximum.
+    fn beat_volume(
+        &mut self,
+        now: Instant,
+        raw_values: &[Frequency],
+        values: &[Frequency],
+        analysis: &mut Analysis,
+    ) {
+        // TODO: only look at the base line?
+        let curr = values.iter().map(|f| f.volume).fold(0.0, f32::max);
+
+        // Silence is detected before the gain control, which would amplify noise.
+        // Bins below a raw volume of 1 count as silent.
+        let curr_unfiltered: f32 = raw_values.iter().map(|f| f.volume.trunc()).sum();
+
+        push_bounded(
+            &mut self.long_historic,
+            LONG_HISTORIC_FRAMES - 1,
+            curr_unfiltered,
+        );
+        push_bounded(&mut self.historic, ROLLING_AVERAGE_FRAMES - 1, curr);
+
+        let max = self.historic.iter().copied().fold(0.0, f32::max);
+        let min = self.historic.iter().copied().fold(f32::MAX, f32::min);
+
+        let long_sum = self.long_historic.iter().sum::<f32>();
+        if long_sum <= 0.0 {
+            if !self.idle {
+                debug!("[AUDIO] Entering sleep mode...");
+            }
+            self.idle = true;
+        } else if self.idle {
+            debug!("[AUDIO] Leaving sleep mode, long = {long_sum}");
+            self.idle = false;
+        }
+        analysis.idle = self.idle;
+
+        let index_mapped = if max > min {
+            ((curr - min) / (max - min) * MAX_BEAT_VOLUME as f32) as u8
+        } else {
+            0
+        };
+
+        if self.last_index == index_mapped {
+            return;
+        }
+
+        if due(now, &mut self.last_beat_publish, SIGNAL_SPEED) {
+            debug!(
+                "index = {index_mapped:02} | curr = {curr:.3} | min = {min:.3} | max = {max:.3}"
+            );
+            self.last_index = index_mapped;
+            analysis.signals.push(Signal::BeatVolume(
+                index_mapped as f32 / MAX_BEAT_VOLUME as f32,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
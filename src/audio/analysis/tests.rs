@@ -0,0 +1,326 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use super::{Analysis, Analyzer};
+use crate::{
+    audio::{
+        file::{AudioFile, FileSource, Playback},
+        sections::{Section, SectionChange},
+        Config as StreamSettings, Signal,
+    },
+    config::Config,
+};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+fn samples(secs: f32) -> usize {
+    (secs * SAMPLE_RATE as f32) as usize
+}
+
+fn silence(secs: f32) -> Vec<f32> {
+    vec![0.0; samples(secs)]
+}
+
+/// Kick drums: short sine bursts falling from 120 Hz to 50 Hz.
+fn kicks(bpm: f32, secs: f32, gain: f32) -> Vec<f32> {
+    let period = samples(60.0 / bpm);
+    let length = samples(0.15);
+    (0..samples(secs))
+        .map(|i| {
+            let t = (i % period) as f32 / SAMPLE_RATE as f32;
+            if i % period >= length {
+                return 0.0;
+            }
+            let freq = 50.0 + 70.0 * (-t * 30.0).exp();
+            gain * (-t * 20.0).exp() * (TAU * freq * t).sin()
+        })
+        .collect()
+}
+
+fn sine(hz: f32, secs: f32, gain: f32) -> Vec<f32> {
+    (0..samples(secs))
+        .map(|i| gain * (TAU * hz * i as f32 / SAMPLE_RATE as f32).sin())
+        .collect()
+}
+
+/// Logarithmic sine sweep.
+fn sweep(from_hz: f32, to_hz: f32, secs: f32, gain: f32) -> Vec<f32> {
+    let n = samples(secs);
+    let ratio = (to_hz / from_hz).ln();
+    (0..n)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let phase = TAU * from_hz * secs / ratio * ((t / secs * ratio).exp() - 1.0);
+            gain * phase.sin()
+        })
+        .collect()
+}
+
+/// Deterministic white noise.
+fn noise(secs: f32, gain: f32) -> Vec<f32> {
+    let mut state: u32 = 0x1234_5678;
+    (0..samples(secs))
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            gain * (state as f32 / u32::MAX as f32 * 2.0 - 1.0)
+        })
+        .collect()
+}
+
+fn mix(a: &[f32], b: &[f32]) -> Vec<f32> {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0.0) + b.get(i).unwrap_or(&0.0))
+        .collect()
+}
+
+/// Scales a clip linearly from `from` to `to`.
+fn fade(clip: Vec<f32>, from: f32, to: f32) -> Vec<f32> {
+    let len = clip.len().max(1) as f32;
+    clip.into_iter()
+        .enumerate()
+        .map(|(i, s)| s * (from + (to - from) * i as f32 / len))
+        .collect()
+}
+
+/// Output of the analysis, with times relative to the start of the clip.
+struct Timeline {
+    frames: Vec<(Duration, Analysis)>,
+}
+
+impl Timeline {
+    fn signals(&self) -> impl Iterator<Item = (Duration, Signal)> + '_ {
+        self.frames
+            .iter()
+            .flat_map(|(time, analysis)| analysis.signals.iter().map(|s| (*time, *s)))
+    }
+
+    fn drops(&self) -> impl Iterator<Item = (Duration, bool)> + '_ {
+        self.frames
+            .iter()
+            .filter_map(|(time, analysis)| analysis.drop.map(|drop| (*time, drop)))
+    }
+
+    fn section_changes(&self) -> Vec<(Duration, SectionChange)> {
+        self.signals()
+            .filter_map(|(time, signal)| match signal {
+                Signal::SectionChange(change) => Some((time, change)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn analyze(clip: Vec<f32>) -> Timeline {
+    let config = Config::default();
+    let file = AudioFile::from_samples(clip, SAMPLE_RATE);
+    let mut source = FileSource::new(file, StreamSettings::default().audio, Playback::Fast, false);
+
+    let start = source.now();
+    let mut analyzer = Analyzer::new(&config, start);
+    let mut frames = vec![];
+    loop {
+        let now = source.now();
+        let Some(spectrum) = source.next_spectrum() else {
+            break;
+        };
+        frames.push((now - start, analyzer.process(now, &spectrum)));
+    }
+
+    Timeline { frames }
+}
+
+fn secs(secs: f32) -> Duration {
+    Duration::from_secs_f32(secs)
+}
+
+#[test]
+fn silence_is_idle_and_quiet() {
+    let timeline = analyze(silence(5.0));
+
+    assert!(timeline.frames.iter().all(|(_, analysis)| analysis.idle));
+    assert!(timeline.drops().all(|(_, drop)| !drop));
+    for (time, signal) in timeline.signals() {
+        match signal {
+            Signal::Volume(level) | Signal::Bass(level) | Signal::BeatVolume(level) => {
+                assert_eq!(level, 0.0, "level at {time:?}")
+            }
+            Signal::Band { energy, onset, .. } => {
+                assert_eq!(energy, 0.0, "band energy at {time:?}");
+                assert!(!onset, "band onset at {time:?}");
+            }
+            Signal::Beat(_) | Signal::BeatAlgo(_) => panic!("beat at {time:?}"),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn kicks_lock_the_tempo() {
+    let timeline = analyze(kicks(120.0, 20.0, 0.8));
+
+    let tempi: Vec<_> = timeline
+        .signals()
+        .filter_map(|(time, signal)| match signal {
+            Signal::Tempo(tempo) if time > secs(8.0) => Some(tempo),
+            _ => None,
+        })
+        .collect();
+    assert!(!tempi.is_empty());
+    for tempo in &tempi {
+        assert!((tempo.bpm - 120.0).abs() < 2.0, "{tempo:?}");
+    }
+    assert!(tempi.last().unwrap().confidence > 0.9);
+
+    let beats: Vec<_> = timeline
+        .signals()
+        .filter(|(time, signal)| *time > secs(8.0) && matches!(signal, Signal::Beat(_)))
+        .map(|(time, _)| time)
+        .collect();
+    assert!(beats.len() >= 20, "{} beats", beats.len());
+    for pair in beats.windows(2) {
+        let interval = pair[1] - pair[0];
+        assert!(
+            interval > secs(0.48) && interval < secs(0.52),
+            "beat interval {interval:?} at {:?}",
+            pair[1]
+        );
+    }
+
+    assert!(timeline
+        .signals()
+        .any(|(_, signal)| matches!(signal, Signal::BeatVolume(level) if level > 0.9)));
+    assert!(timeline.drops().all(|(_, drop)| !drop));
+    assert!(timeline
+        .frames
+        .iter()
+        .all(|(time, analysis)| *time < secs(1.0) || !analysis.idle));
+}
+
+#[test]
+fn sweep_moves_through_the_bands() {
+    let timeline = analyze(sweep(30.0, 16000.0, 10.0, 0.5));
+
+    // When each band was the loudest.
+    let mut peaks: Vec<(Duration, f32)> = vec![];
+    for (time, signal) in timeline.signals() {
+        let Signal::Band { id, energy, .. } = signal else {
+            continue;
+        };
+        let id = id as usize;
+        if peaks.len() <= id {
+            peaks.resize(id + 1, (Duration::ZERO, 0.0));
+        }
+        if energy > peaks[id].1 {
+            peaks[id] = (time, energy);
+        }
+    }
+
+    assert_eq!(peaks.len(), Config::default().bands.len());
+    for pair in peaks.windows(2) {
+        assert!(pair[0].0 < pair[1].0, "band peaks out of order: {peaks:?}");
+    }
+}
+
+#[test]
+fn noise_has_no_confident_tempo() {
+    let timeline = analyze(noise(16.0, 0.2));
+
+    for (time, signal) in timeline.signals() {
+        if let Signal::Tempo(tempo) = signal {
+            assert!(tempo.confidence < 0.6, "{tempo:?} at {time:?}");
+        }
+    }
+    assert!(timeline.drops().all(|(_, drop)| !drop));
+}
+
+#[test]
+fn drop_follows_the_buildup() {
+    let buildup = noise(16.0, 0.2);
+    let drop = mix(
+        &mix(&kicks(128.0, 16.0, 0.5), &sine(55.0, 16.0, 0.5)),
+        &noise(16.0, 0.1),
+    );
+    let timeline = analyze([buildup, drop].concat());
+
+    let first_drop = timeline
+        .drops()
+        .find(|(_, drop)| *drop)
+        .map(|(time, _)| time)
+        .expect("no drop detected");
+    assert!(
+        first_drop > secs(16.0) && first_drop < secs(24.0),
+        "drop at {first_drop:?}"
+    );
+    assert!(timeline
+        .drops()
+        .filter(|(time, _)| *time > first_drop)
+        .all(|(_, drop)| drop));
+
+    // The bass signal is muted during the drop.
+    assert!(timeline
+        .signals()
+        .filter(|(time, _)| *time > first_drop + secs(1.0))
+        .all(|(_, signal)| !matches!(signal, Signal::Bass(level) if level > 0.0)));
+}
+
+#[test]
+fn sections_follow_the_song() {
+    let song = [
+        sine(440.0, 8.0, 0.2),
+        fade(noise(8.0, 1.0), 0.02, 0.32),
+        mix(
+            &mix(&kicks(128.0, 16.0, 0.5), &sine(55.0, 16.0, 0.5)),
+            &noise(16.0, 0.1),
+        ),
+        mix(&sine(440.0, 10.0, 0.2), &noise(10.0, 0.03)),
+        fade(sine(440.0, 6.0, 0.2), 1.0, 0.0),
+        silence(3.0),
+    ]
+    .concat();
+    let timeline = analyze(song);
+
+    let changes = timeline.section_changes();
+    let expected = [
+        (Section::Intro, Section::Buildup, 8.0),
+        (Section::Buildup, Section::Drop, 16.0),
+        (Section::Drop, Section::Breakdown, 32.0),
+        (Section::Breakdown, Section::Outro, 42.0),
+    ];
+    assert_eq!(changes.len(), expected.len(), "{changes:?}");
+    for ((time, change), (from, to, start)) in changes.iter().zip(expected) {
+        assert_eq!((change.from, change.to), (from, to), "{changes:?}");
+        assert!(
+            *time > secs(start) && *time < secs(start + 4.0),
+            "{from} -> {to} at {time:?}"
+        );
+    }
+
+    assert!(timeline.signals().any(|(time, signal)| matches!(
+        signal,
+        Signal::Section(state) if time > secs(25.0) && time < secs(30.0)
+            && state.section == Section::Drop
+            && state.confidence == 1.0
+    )));
+}
+
+#[test]
+fn steady_kicks_are_neither_buildup_nor_drop() {
+    let timeline = analyze(kicks(120.0, 20.0, 0.8));
+
+    for (time, change) in timeline.section_changes() {
+        assert!(
+            !matches!(change.to, Section::Buildup | Section::Drop),
+            "{} -> {} at {time:?}",
+            change.from,
+            change.to
+        );
+    }
+}
+
+#[test]
+fn silence_stays_in_the_intro() {
+    let timeline = analyze(silence(10.0));
+
+    assert!(timeline.section_changes().is_empty());
+}
@@ -0,0 +1,201 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use audioviz::spectrum::Frequency;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::smoothing_factor;
+
+/// Index of a band in the configured list.
+pub type BandId = u8;
+
+/// The peak value is held this long before it starts to fall.
+const PEAK_HOLD: Duration = Duration::from_millis(500);
+/// Fraction of the peak which is lost per second after the hold time.
+const PEAK_DECAY_PER_SEC: f32 = 1.5;
+/// Time constant of the long term average which onsets are compared against.
+const AVERAGE_TIME_CONSTANT: Duration = Duration::from_secs(2);
+/// Two onsets of the same band are at least this far apart.
+const MIN_ONSET_INTERVAL: Duration = Duration::from_millis(80);
+
+/// A frequency band, configured by the user.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandConfig {
+    pub name: String,
+    pub low_hz: f32,
+    pub high_hz: f32,
+    /// Time constant of the smoothed energy in milliseconds, 0 is no smoothing.
+    #[serde(default = "default_smoothing_ms")]
+    pub smoothing_ms: u64,
+    /// An onset is detected if the energy exceeds the long term average by this factor.
+    #[serde(default = "default_onset_ratio")]
+    pub onset_ratio: f32,
+}
+
+fn default_smoothing_ms() -> u64 {
+    50
+}
+
+fn default_onset_ratio() -> f32 {
+    1.5
+}
+
+impl BandConfig {
+    fn new(name: &str, low_hz: f32, high_hz: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            low_hz,
+            high_hz,
+            smoothing_ms: default_smoothing_ms(),
+            onset_ratio: default_onset_ratio(),
+        }
+    }
+
+    pub fn default_bands() -> Vec<Self> {
+        vec![
+            Self::new("sub", 20.0, 60.0),
+            Self::new("bass", 60.0, 250.0),
+            Self::new("low_mid", 250.0, 500.0),
+            Self::new("mid", 500.0, 2000.0),
+            Self::new("high", 2000.0, 6000.0),
+            Self::new("air", 6000.0, 20000.0),
+        ]
+    }
+}
+
+pub fn validate_bands(bands: &[BandConfig]) -> Result<()> {
+    if bands.len() > BandId::MAX as usize + 1 {
+        bail!(
+            "At most {} bands can be configured",
+            BandId::MAX as usize + 1
+        );
+    }
+
+    for (i, band) in bands.iter().enumerate() {
+        if !(band.low_hz >= 0.0 && band.low_hz < band.high_hz) {
+            bail!(
+                "Band `{}`: {} Hz to {} Hz is not a valid range",
+                band.name,
+                band.low_hz,
+                band.high_hz
+            );
+        }
+
+        if band.onset_ratio.is_nan() || band.onset_ratio < 1.0 {
+            bail!("Band `{}`: `onset_ratio` must be at least 1", band.name);
+        }
+
+        if bands[..i].iter().any(|b| b.name == band.name) {
+            bail!("Band `{}` is configured more than once", band.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Analysis result of one band.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BandLevel {
+    /// Smoothed energy, `0.0..=1.0`.
+    pub energy: f32,
+    /// Highest recent energy, held for a moment and then falling.
+    pub peak: f32,
+    /// True if the band had an onset since the last report.
+    pub onset: bool,
+}
+
+struct BandState {
+    config: BandConfig,
+    level: BandLevel,
+    average: f32,
+    /// Whether the band was above the onset threshold in the last spectrum.
+    above: bool,
+    peak_time: Option<Instant>,
+    last_onset: Option<Instant>,
+    last_update: Option<Instant>,
+}
+
+/// Splits spectra into the configured bands.
+pub struct BandAnalyzer {
+    bands: Vec<BandState>,
+}
+
+impl BandAnalyzer {
+    pub fn new(bands: &[BandConfig]) -> Self {
+        Self {
+            bands: bands
+                .iter()
+                .cloned()
+                .map(|config| BandState {
+                    config,
+                    level: BandLevel::default(),
+                    average: 0.0,
+                    above: false,
+                    peak_time: None,
+                    last_onset: None,
+                    last_update: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn process(&mut self, now: Instant, spectrum: &[Frequency]) {
+        for band in &mut self.bands {
+            let (sum, count) = spectrum
+                .iter()
+                .filter(|f| f.freq >= band.config.low_hz && f.freq < band.config.high_hz)
+                .fold((0.0, 0), |(sum, count), f| (sum + f.volume, count + 1));
+            let value = if count == 0 { 0.0 } else { sum / count as f32 };
+
+            let elapsed = band
+                .last_update
+                .map_or(Duration::ZERO, |last| now.duration_since(last));
+            band.last_update = Some(now);
+
+            let level = &mut band.level;
+            level.energy += (value - level.energy)
+                * smoothing_factor(elapsed, Duration::from_millis(band.config.smoothing_ms));
+
+            // Peak hold.
+            if level.energy >= level.peak {
+                level.peak = level.energy;
+                band.peak_time = Some(now);
+            } else if band
+                .peak_time
+                .map_or(true, |time| now.duration_since(time) > PEAK_HOLD)
+            {
+                level.peak = (level.peak
+                    * (1.0 - PEAK_DECAY_PER_SEC * elapsed.as_secs_f32()).max(0.0))
+                .max(level.energy);
+            }
+
+            // Onsets are rises well above the long term average.
+            let above = value > band.average * band.config.onset_ratio && value > f32::EPSILON;
+            let rising = above && !band.above;
+            band.above = above;
+            band.average +=
+                (value - band.average) * smoothing_factor(elapsed, AVERAGE_TIME_CONSTANT);
+            if rising
+                && band
+                    .last_onset
+                    .map_or(true, |last| now.duration_since(last) >= MIN_ONSET_INTERVAL)
+            {
+                band.last_onset = Some(now);
+                level.onset = true;
+            }
+        }
+    }
+
+    /// Current levels of all bands, clears the onset flags.
+    pub fn report(&mut self) -> Vec<(BandId, BandLevel)> {
+        self.bands
+            .iter_mut()
+            .enumerate()
+            .map(|(id, band)| {
+                let level = band.level;
+                band.level.onset = false;
+                (id as BandId, level)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::tempo::Tempo;
+
+/// Taps further apart than this start a new measurement.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_TAPS: usize = 8;
+/// How far a single nudge moves the beat grid.
+const NUDGE_STEP: Duration = Duration::from_millis(10);
+
+pub const MIN_BPM: f32 = 30.0;
+pub const MAX_BPM: f32 = 300.0;
+
+/// What drives the tempo of the beat clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSource {
+    /// Tempo and beats of the audio tempo tracker.
+    #[default]
+    Tracker,
+    /// Tempo tapped by the user.
+    Tap,
+    /// A fixed tempo.
+    Manual,
+}
+
+/// Commands for the beat clock, e.g. from the GUI.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockControl {
+    Tap,
+    /// Moves the beat grid earlier.
+    NudgeForward,
+    /// Moves the beat grid later.
+    NudgeBack,
+    /// Makes now the first beat of a bar.
+    Resync,
+    /// Switches to a fixed tempo.
+    Manual(f32),
+    Source(ClockSource),
+}
+
+/// Snapshot of the clock for displays.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ClockState {
+    pub source: ClockSource,
+    /// 0 if the clock has no tempo yet.
+    pub bpm: f32,
+    /// Beat inside the bar, 0 is the downbeat.
+    pub beat: u8,
+    pub beats_per_bar: u8,
+    /// Position inside the current beat, `0.0..1.0`.
+    pub phase: f32,
+}
+
+/// A beat emitted by the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockBeat {
+    /// Beat inside the bar, 0 is the downbeat.
+    pub beat: u8,
+    /// Number of bars since the clock started, wrapping.
+    pub bar: u8,
+}
+
+/// Keeps bar, beat and phase, driven by the tempo tracker, tap tempo or a manual tempo.
+pub struct BeatClock {
+    source: ClockSource,
+    bpm: f32,
+    beats_per_bar: u8,
+    /// The beat which was emitted last, `None` after a resync.
+    beat: Option<u8>,
+    bar: u8,
+    last_beat: Option<Instant>,
+    next_beat: Option<Instant>,
+    taps: VecDeque<Instant>,
+}
+
+impl BeatClock {
+    pub fn new(beats_per_bar: u8) -> Self {
+        Self {
+            source: ClockSource::Tracker,
+            bpm: 0.0,
+            beats_per_bar: beats_per_bar.max(1),
+            beat: None,
+            bar: 0,
+            last_beat: None,
+            next_beat: None,
+            taps: VecDeque::with_capacity(MAX_TAPS),
+        }
+    }
+
+    fn period(&self) -> Option<Duration> {
+        (self.bpm > 0.0).then(|| Duration::from_secs_f32(60.0 / self.bpm))
+    }
+
+    pub fn control(&mut self, control: ClockControl, now: Instant) {
+        match control {
+            ClockControl::Tap => self.tap(now),
+            ClockControl::NudgeForward => {
+                self.next_beat = self
+                    .next_beat
+                    .map(|next| next.checked_sub(NUDGE_STEP).unwrap_or(next));
+            }
+            ClockControl::NudgeBack => {
+                self.next_beat = self.next_beat.map(|next| next + NUDGE_STEP);
+            }
+            ClockControl::Resync => {
+                self.beat = None;
+                self.next_beat = Some(now);
+            }
+            ClockControl::Manual(bpm) => {
+                self.source = ClockSource::Manual;
+                self.set_bpm(bpm, now);
+            }
+            ClockControl::Source(source) => self.source = source,
+        }
+    }
+
+    fn tap(&mut self, now: Instant) {
+        if self
+            .taps
+            .back()
+            .is_some_and(|last| now.duration_since(*last) > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+
+        if self.taps.len() >= MAX_TAPS {
+            self.taps.pop_front();
+        }
+        self.taps.push_back(now);
+
+        let (Some(first), Some(last)) = (self.taps.front(), self.taps.back()) else {
+            return;
+        };
+        if self.taps.len() < 2 {
+            return;
+        }
+
+        let interval = last.duration_since(*first).as_secs_f32() / (self.taps.len() - 1) as f32;
+        self.source = ClockSource::Tap;
+        self.set_bpm(60.0 / interval, now);
+
+        // Every tap is a beat.
+        self.next_beat = Some(now);
+    }
+
+    fn set_bpm(&mut self, bpm: f32, now: Instant) {
+        // `clamp` keeps NaN, which would stop the clock.
+        if !bpm.is_finite() {
+            return;
+        }
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+        if self.next_beat.is_none() {
+            self.next_beat = Some(now);
+        }
+    }
+
+    /// Follows the tempo tracker if it is the clock's source.
+    /// `beat` is true if the tracker reported a (confident) beat.
+    pub fn tracker(&mut self, tempo: Tempo, beat: bool, now: Instant) {
+        if self.source != ClockSource::Tracker || !tempo.bpm.is_finite() || tempo.bpm <= 0.0 {
+            return;
+        }
+
+        self.bpm = tempo.bpm.clamp(MIN_BPM, MAX_BPM);
+        let Some(period) = self.period() else {
+            return;
+        };
+
+        if !beat {
+            return;
+        }
+
+        // A tracker beat close to the one the clock just emitted only corrects the grid.
+        if self
+            .last_beat
+            .is_some_and(|last| now.duration_since(last) < period / 2)
+        {
+            self.next_beat = Some(now + period);
+        } else {
+            self.next_beat = Some(now);
+        }
+    }
+
+    /// Returns the beat if one is due.
+    pub fn process(&mut self, now: Instant) -> Option<ClockBeat> {
+        let period = self.period()?;
+        let next_beat = self.next_beat?;
+        if now < next_beat {
+            return None;
+        }
+
+        let mut next_beat = next_beat + period;
+        while next_beat <= now {
+            next_beat += period;
+        }
+        self.next_beat = Some(next_beat);
+        self.last_beat = Some(now);
+
+        let beat = match self.beat {
+            Some(beat) => (beat + 1) % self.beats_per_bar,
+            None => 0,
+        };
+        if beat == 0 && self.beat.is_some() {
+            self.bar = self.bar.wrapping_add(1);
+        }
+        self.beat = Some(beat);
+
+        Some(ClockBeat {
+            beat,
+            bar: self.bar,
+        })
+    }
+
+    pub fn state(&self, now: Instant) -> ClockState {
+        let phase = match (self.period(), self.next_beat) {
+            (Some(period), Some(next_beat)) if next_beat > now => {
+                let remaining = next_beat.duration_since(now).as_secs_f32();
+                (1.0 - remaining / period.as_secs_f32()).clamp(0.0, 0.999)
+            }
+            _ => 0.0,
+        };
+
+        ClockState {
+            source: self.source,
+            bpm: self.bpm,
+            beat: self.beat.unwrap_or(0),
+            beats_per_bar: self.beats_per_bar,
+            phase,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use super::{BeatClock, ClockBeat, ClockControl, ClockSource, MAX_BPM, MIN_BPM};
+use crate::audio::tempo::Tempo;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// A clock at a manual 120 BPM (a beat every 500ms) which starts at `start`.
+fn manual_clock(start: Instant) -> BeatClock {
+    let mut clock = BeatClock::new(4);
+    clock.control(ClockControl::Manual(120.0), start);
+    clock
+}
+
+fn tempo(bpm: f32) -> Tempo {
+    Tempo {
+        bpm,
+        phase: 0.0,
+        confidence: 1.0,
+    }
+}
+
+#[test]
+fn taps_are_averaged() {
+    let start = Instant::now();
+    let mut clock = BeatClock::new(4);
+
+    clock.control(ClockControl::Tap, start);
+    assert_eq!(clock.state(start).bpm, 0.0);
+
+    // 500ms on average.
+    for tap in [450, 1000, 1500] {
+        clock.control(ClockControl::Tap, start + ms(tap));
+    }
+    let state = clock.state(start + ms(1500));
+    assert_eq!(state.source, ClockSource::Tap);
+    assert_eq!(state.bpm, 120.0);
+
+    // Every tap is a beat.
+    assert!(clock.process(start + ms(1500)).is_some());
+
+    // A pause starts a new measurement.
+    let restart = start + ms(5000);
+    clock.control(ClockControl::Tap, restart);
+    clock.control(ClockControl::Tap, restart + ms(1000));
+    assert_eq!(clock.state(restart).bpm, 60.0);
+}
+
+#[test]
+fn tempos_are_clamped() {
+    let start = Instant::now();
+    let mut clock = BeatClock::new(4);
+
+    clock.control(ClockControl::Tap, start);
+    clock.control(ClockControl::Tap, start + ms(100));
+    assert_eq!(clock.state(start).bpm, MAX_BPM);
+
+    clock.control(ClockControl::Manual(10.0), start);
+    assert_eq!(clock.state(start).bpm, MIN_BPM);
+    clock.control(ClockControl::Manual(f32::NAN), start);
+    assert_eq!(clock.state(start).bpm, MIN_BPM);
+
+    clock.control(ClockControl::Source(ClockSource::Tracker), start);
+    clock.tracker(tempo(1000.0), false, start);
+    assert_eq!(clock.state(start).bpm, MAX_BPM);
+    clock.tracker(tempo(5.0), false, start);
+    assert_eq!(clock.state(start).bpm, MIN_BPM);
+    clock.tracker(tempo(f32::NAN), false, start);
+    clock.tracker(tempo(0.0), false, start);
+    assert_eq!(clock.state(start).bpm, MIN_BPM);
+}
+
+#[test]
+fn the_tracker_only_drives_its_own_source() {
+    let start = Instant::now();
+    let mut clock = manual_clock(start);
+
+    clock.tracker(tempo(140.0), true, start);
+    assert_eq!(clock.state(start).bpm, 120.0);
+
+    clock.control(ClockControl::Source(ClockSource::Tracker), start);
+    clock.tracker(tempo(140.0), true, start);
+    assert_eq!(clock.state(start).bpm, 140.0);
+}
+
+#[test]
+fn beats_count_through_bars() {
+    let start = Instant::now();
+    let mut clock = manual_clock(start);
+
+    let beats: Vec<_> = (0..6)
+        .map(|beat| clock.process(start + ms(500 * beat)).unwrap())
+        .collect();
+    assert_eq!(
+        beats.iter().map(|b| (b.beat, b.bar)).collect::<Vec<_>>(),
+        vec![(0, 0), (1, 0), (2, 0), (3, 0), (0, 1), (1, 1)]
+    );
+
+    // Between beats.
+    assert_eq!(clock.process(start + ms(2750)), None);
+    let state = clock.state(start + ms(2750));
+    assert_eq!(state.beat, 1);
+    assert_eq!(state.beats_per_bar, 4);
+    assert_eq!(state.phase, 0.5);
+}
+
+#[test]
+fn nudges_move_the_next_beat() {
+    let start = Instant::now();
+    let mut clock = manual_clock(start);
+    clock.process(start);
+
+    clock.control(ClockControl::NudgeForward, start);
+    assert_eq!(clock.process(start + ms(489)), None);
+    assert!(clock.process(start + ms(490)).is_some());
+
+    clock.control(ClockControl::NudgeBack, start + ms(490));
+    clock.control(ClockControl::NudgeBack, start + ms(490));
+    assert_eq!(clock.process(start + ms(1000)), None);
+    assert!(clock.process(start + ms(1010)).is_some());
+}
+
+#[test]
+fn resync_starts_a_bar_now() {
+    let start = Instant::now();
+    let mut clock = manual_clock(start);
+    clock.process(start);
+    clock.process(start + ms(500));
+
+    clock.control(ClockControl::Resync, start + ms(700));
+    assert_eq!(
+        clock.process(start + ms(700)),
+        Some(ClockBeat { beat: 0, bar: 0 })
+    );
+    assert_eq!(clock.process(start + ms(1000)), None);
+    assert_eq!(
+        clock.process(start + ms(1200)),
+        Some(ClockBeat { beat: 1, bar: 0 })
+    );
+}
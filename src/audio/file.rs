@@ -0,0 +1,274 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use audioviz::spectrum::{config::StreamConfig, processor::Processor, Frequency};
+use serde::{Deserialize, Serialize};
+
+/// How fast a file is streamed through the analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Playback {
+    /// As fast as it would be played, e.g. to rehearse a show.
+    #[default]
+    Realtime,
+    /// As fast as possible, timestamps are derived from the position in the file.
+    Fast,
+}
+
+/// An audio file which is analyzed instead of a live input device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileInput {
+    /// A WAV or FLAC file.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub playback: Playback,
+    /// Starts over at the end of the file instead of ending the analysis.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+impl fmt::Display for FileInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.to_string_lossy())
+    }
+}
+
+/// Decoded samples of an audio file, mixed down to mono.
+pub struct AudioFile {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl AudioFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+
+        let file = match extension.as_deref() {
+            Some("wav") => Self::read_wav(path),
+            Some("flac") => Self::read_flac(path),
+            _ => bail!(
+                "`{}` is not supported, only WAV and FLAC files are",
+                path.to_string_lossy()
+            ),
+        };
+
+        let file = file.with_context(|| format!("Could not read `{}`", path.to_string_lossy()))?;
+
+        // The playback position and duration are divided by it.
+        if file.sample_rate == 0 {
+            bail!("`{}` has a sample rate of 0 Hz", path.to_string_lossy());
+        }
+
+        Ok(file)
+    }
+
+    pub fn from_samples(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            sample_rate,
+        }
+    }
+
+    fn read_wav(path: &Path) -> Result<Self> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        Ok(Self {
+            samples: mix_down(&samples, spec.channels as usize),
+            sample_rate: spec.sample_rate,
+        })
+    }
+
+    fn read_flac(path: &Path) -> Result<Self> {
+        let mut reader = claxon::FlacReader::open(path)?;
+        let info = reader.streaminfo();
+        let scale = (1i64 << (info.bits_per_sample - 1)) as f32;
+
+        let samples: Vec<f32> = reader
+            .samples()
+            .map(|sample| sample.map(|s| s as f32 / scale))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            samples: mix_down(&samples, info.channels as usize),
+            sample_rate: info.sample_rate,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate as f64)
+    }
+}
+
+/// Averages interleaved channels, the analysis works on a single channel.
+fn mix_down(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Produces spectra from an audio file the same way the live stream does,
+/// one per refresh of the stream config.
+pub struct FileSource {
+    file: AudioFile,
+    config: StreamConfig,
+    playback: Playback,
+    repeat: bool,
+    /// Position of the next spectrum in the file, in samples.
+    position: usize,
+    /// Samples which have been analyzed in total, including repetitions.
+    played: u64,
+    /// Samples between two spectra.
+    hop: usize,
+    start: Instant,
+    // Gravity, as applied by `audioviz`'s stream.
+    freq_buffer: Vec<Frequency>,
+    gravity_time_buffer: Vec<u32>,
+}
+
+impl FileSource {
+    pub fn open(input: &FileInput, config: StreamConfig) -> Result<Self> {
+        let file = AudioFile::open(&input.path)?;
+        Ok(Self::new(file, config, input.playback, input.repeat))
+    }
+
+    pub fn new(
+        file: AudioFile,
+        mut config: StreamConfig,
+        playback: Playback,
+        repeat: bool,
+    ) -> Self {
+        config.processor.sample_rate = file.sample_rate;
+        let hop = (file.sample_rate as usize / config.refresh_rate.max(1)).max(1);
+
+        Self {
+            file,
+            config,
+            playback,
+            repeat,
+            position: 0,
+            played: 0,
+            hop,
+            start: Instant::now(),
+            freq_buffer: vec![],
+            gravity_time_buffer: vec![],
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.file.duration()
+    }
+
+    pub fn playback(&self) -> Playback {
+        self.playback
+    }
+
+    /// Timestamp of the next spectrum.
+    pub fn now(&self) -> Instant {
+        self.start + Duration::from_secs_f64(self.played as f64 / self.file.sample_rate as f64)
+    }
+
+    /// Returns `None` at the end of the file.
+    /// In realtime playback this waits until the spectrum is due.
+    pub fn next_spectrum(&mut self) -> Option<Vec<Frequency>> {
+        if self.position >= self.file.samples.len() {
+            if !self.repeat || self.file.samples.is_empty() {
+                return None;
+            }
+            self.position = 0;
+        }
+
+        if self.playback == Playback::Realtime {
+            let due = self.now();
+            let now = Instant::now();
+            if due > now {
+                spin_sleep::sleep(due - now);
+            }
+        }
+
+        let end = (self.position + self.hop).min(self.file.samples.len());
+        self.played += (end - self.position) as u64;
+        self.position = end;
+
+        Some(self.spectrum(end))
+    }
+
+    /// Spectrum of the window which ends at `end`.
+    fn spectrum(&mut self, end: usize) -> Vec<Frequency> {
+        let fft_resolution = self.config.fft_resolution;
+        let start = end.saturating_sub(fft_resolution);
+
+        // Zero padded at the beginning of the file.
+        let mut window = vec![0.0; fft_resolution - (end - start)];
+        window.extend_from_slice(&self.file.samples[start..end]);
+
+        let mut processor = Processor::from_raw_data(self.config.processor.clone(), window);
+        processor.apodize();
+        processor.fft();
+        processor.normalize_frequency_volume();
+        processor.raw_to_freq_buffer();
+        processor.normalize_frequency_position();
+        processor.distribute_frequency_position();
+
+        self.apply_gravity(processor.freq_buffer);
+
+        let mut processor =
+            Processor::from_frequencies(self.config.processor.clone(), self.freq_buffer.clone());
+        processor.bound_frequencies();
+        processor.interpolate();
+        processor.freq_buffer
+    }
+
+    fn apply_gravity(&mut self, processed: Vec<Frequency>) {
+        let Some(gravity) = self.config.gravity else {
+            self.freq_buffer = processed;
+            return;
+        };
+
+        if self.freq_buffer.len() != processed.len() {
+            self.freq_buffer = vec![Frequency::empty(); processed.len()];
+        }
+        if self.gravity_time_buffer.len() != processed.len() {
+            self.gravity_time_buffer = vec![0; processed.len()];
+        }
+
+        for (i, frequency) in processed.into_iter().enumerate() {
+            if self.freq_buffer[i].volume < frequency.volume {
+                self.freq_buffer[i] = frequency;
+                self.gravity_time_buffer[i] = 0;
+            } else {
+                self.gravity_time_buffer[i] += 1;
+            }
+        }
+
+        for (frequency, time) in self.freq_buffer.iter_mut().zip(&self.gravity_time_buffer) {
+            frequency.volume -= gravity * 0.0025 * (*time as f32);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use audioviz::spectrum::Frequency;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::smoothing_factor;
+
+/// The song is classified this often.
+const EVALUATION_INTERVAL: Duration = Duration::from_millis(250);
+/// Number of recent classifications which vote on the section, about 2 seconds.
+const VOTES: usize = 8;
+/// Share of the votes a new section needs to be entered.
+const MIN_VOTE_SHARE: f32 = 0.75;
+/// A section is held at least this long, drops may start right after a buildup though.
+const MIN_SECTION_DURATION: Duration = Duration::from_secs(4);
+
+const SHORT_TIME_CONSTANT: Duration = Duration::from_secs(1);
+const LONG_TIME_CONSTANT: Duration = Duration::from_secs(30);
+/// Trends are measured over this many evaluations, about 6 seconds.
+const TREND_LEN: usize = 24;
+
+/// The loudest raw bin is below this level during silence.
+const SILENCE_LEVEL: f32 = 0.01;
+/// Bins up to this frequency are bass, bins above `HIGH_MIN_FREQ` make up the brightness.
+const BASS_MAX_FREQ: f32 = 150.0;
+const HIGH_MIN_FREQ: f32 = 4000.0;
+/// Normalized bass levels (smoothed) of a groove and of a drop.
+const GROOVE_BASS: f32 = 0.15;
+const DROP_BASS: f32 = 0.35;
+/// A rise of the energy by this factor over the trend window is a buildup,
+/// as is a rise of the brightness (share of the high frequencies) by `RISING_BRIGHTNESS`.
+const RISING_ENERGY: f32 = 1.5;
+const RISING_BRIGHTNESS: f32 = 0.1;
+/// Without a groove, the song fades out below this share of the long term energy.
+const OUTRO_ENERGY: f32 = 0.3;
+
+/// Part of a song, as classified from the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    #[default]
+    Intro,
+    Verse,
+    /// Rising energy or noise sweeps before a drop.
+    Buildup,
+    Drop,
+    Breakdown,
+    Outro,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Section::Intro => "intro",
+            Section::Verse => "verse",
+            Section::Buildup => "buildup",
+            Section::Drop => "drop",
+            Section::Breakdown => "breakdown",
+            Section::Outro => "outro",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The current section, published periodically.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct SectionState {
+    pub section: Section,
+    /// Share of the recent classifications which agree with the section, `0.0..=1.0`.
+    pub confidence: f32,
+}
+
+/// A transition between two sections.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SectionChange {
+    pub from: Section,
+    pub to: Section,
+    pub confidence: f32,
+}
+
+/// Features of the recent spectra, smoothed.
+#[derive(Debug, Clone, Copy, Default)]
+struct Features {
+    /// Mean raw volume, short and long term.
+    energy: f32,
+    long_energy: f32,
+    /// Share of the raw volume above `HIGH_MIN_FREQ`.
+    brightness: f32,
+    /// Loudest normalized bass bin.
+    bass: f32,
+    silent: bool,
+}
+
+/// Classifies the song into sections, with hysteresis.
+pub struct SectionTracker {
+    features: Option<Features>,
+    last_update: Option<Instant>,
+    last_evaluation: Option<Instant>,
+    /// Energy and brightness at the recent evaluations, for the trends.
+    trend: VecDeque<(f32, f32)>,
+    votes: VecDeque<Section>,
+
+    section: Section,
+    since: Option<Instant>,
+    confidence: f32,
+}
+
+impl Default for SectionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SectionTracker {
+    pub fn new() -> Self {
+        Self {
+            features: None,
+            last_update: None,
+            last_evaluation: None,
+            trend: VecDeque::with_capacity(TREND_LEN),
+            votes: VecDeque::with_capacity(VOTES),
+            section: Section::Intro,
+            since: None,
+            confidence: 0.0,
+        }
+    }
+
+    pub fn state(&self) -> SectionState {
+        SectionState {
+            section: self.section,
+            confidence: self.confidence,
+        }
+    }
+
+    /// Feeds a spectrum, before (`raw`) and after (`normalized`) the gain control.
+    /// Returns the transition if the section changed.
+    pub fn process(
+        &mut self,
+        now: Instant,
+        raw: &[Frequency],
+        normalized: &[Frequency],
+    ) -> Option<SectionChange> {
+        self.update_features(now, raw, normalized);
+
+        if self
+            .last_evaluation
+            .is_some_and(|last| now.duration_since(last) < EVALUATION_INTERVAL)
+        {
+            return None;
+        }
+        self.last_evaluation = Some(now);
+        let since = *self.since.get_or_insert(now);
+
+        let features = self.features?;
+        if self.trend.len() >= TREND_LEN {
+            self.trend.pop_front();
+        }
+        self.trend.push_back((features.energy, features.brightness));
+
+        let vote = self.classify(&features);
+        if self.votes.len() >= VOTES {
+            self.votes.pop_front();
+        }
+        self.votes.push_back(vote);
+
+        let share = |section: Section| {
+            self.votes.iter().filter(|vote| **vote == section).count() as f32 / VOTES as f32
+        };
+        self.confidence = share(self.section);
+
+        let held = now.duration_since(since) >= MIN_SECTION_DURATION
+            || (self.section == Section::Buildup && vote == Section::Drop);
+        if vote == self.section || !held || share(vote) < MIN_VOTE_SHARE {
+            return None;
+        }
+
+        let change = SectionChange {
+            from: self.section,
+            to: vote,
+            confidence: share(vote),
+        };
+        self.section = vote;
+        self.since = Some(now);
+        self.confidence = change.confidence;
+        Some(change)
+    }
+
+    fn update_features(&mut self, now: Instant, raw: &[Frequency], normalized: &[Frequency]) {
+        let elapsed = self
+            .last_update
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last_update = Some(now);
+
+        let (total, high) = raw.iter().fold((0.0, 0.0), |(total, high), f| {
+            let volume = f.volume.max(0.0);
+            let high_volume = if f.freq >= HIGH_MIN_FREQ { volume } else { 0.0 };
+            (total + volume, high + high_volume)
+        });
+        let energy = total / raw.len().max(1) as f32;
+        let peak = raw.iter().map(|f| f.volume).fold(0.0, f32::max);
+        let brightness = if total > 0.0 { high / total } else { 0.0 };
+        let bass = normalized
+            .iter()
+            .filter(|f| f.freq <= BASS_MAX_FREQ)
+            .map(|f| f.volume)
+            .fold(0.0, f32::max);
+
+        let features = self.features.get_or_insert(Features {
+            energy,
+            long_energy: energy,
+            brightness,
+            bass,
+            silent: false,
+        });
+
+        let short = smoothing_factor(elapsed, SHORT_TIME_CONSTANT);
+        features.energy += (energy - features.energy) * short;
+        features.bass += (bass - features.bass) * short;
+        features.long_energy +=
+            (energy - features.long_energy) * smoothing_factor(elapsed, LONG_TIME_CONSTANT);
+        features.silent = peak < SILENCE_LEVEL;
+        // Silence has no color, the brightness of the music is kept.
+        if !features.silent {
+            features.brightness += (brightness - features.brightness) * short;
+        }
+    }
+
+    /// The section the current features suggest.
+    fn classify(&self, features: &Features) -> Section {
+        let started = self.section != Section::Intro;
+
+        if features.silent {
+            return if started {
+                Section::Outro
+            } else {
+                Section::Intro
+            };
+        }
+
+        if features.bass >= DROP_BASS {
+            return Section::Drop;
+        }
+
+        if self.rising() {
+            return Section::Buildup;
+        }
+
+        let level = if features.long_energy > 0.0 {
+            features.energy / features.long_energy
+        } else {
+            1.0
+        };
+
+        if features.bass >= GROOVE_BASS {
+            Section::Verse
+        } else if !started {
+            Section::Intro
+        } else if level < OUTRO_ENERGY {
+            Section::Outro
+        } else {
+            Section::Breakdown
+        }
+    }
+
+    /// Whether the energy or the brightness (at a steady energy) have been rising over the trend window.
+    fn rising(&self) -> bool {
+        if self.trend.len() < TREND_LEN {
+            return false;
+        }
+
+        let quarter = TREND_LEN / 4;
+        let mean = |values: &mut dyn Iterator<Item = (f32, f32)>| {
+            let (energy, brightness) = values.fold((0.0, 0.0), |(energy, brightness), (e, b)| {
+                (energy + e, brightness + b)
+            });
+            (energy / quarter as f32, brightness / quarter as f32)
+        };
+        let (first_energy, first_brightness) = mean(&mut self.trend.iter().copied().take(quarter));
+        let (last_energy, last_brightness) =
+            mean(&mut self.trend.iter().copied().skip(TREND_LEN - quarter));
+
+        // Fading out leaves noise, which is bright as well.
+        last_energy > first_energy * RISING_ENERGY
+            || (last_brightness > first_brightness + RISING_BRIGHTNESS
+                && last_energy >= first_energy)
+    }
+}
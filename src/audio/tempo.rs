@@ -0,0 +1,304 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The onset envelope is resampled to a fixed rate, independent of the analysis loop speed.
+/// A hop is the mean flux of the spectra in it, hops without a spectrum repeat the previous one.
+/// Taking the maximum instead would favour hops with more spectra, which (as spectra arrive at
+/// a fixed rate) makes even white noise look periodic.
+const HOP: Duration = Duration::from_millis(10);
+const ENVELOPE_RATE: f32 = 100.0;
+/// About 8 seconds of envelope are used for the estimate.
+const HISTORY_LEN: usize = 800;
+/// Estimates are only made once enough history is available.
+const MIN_HISTORY_LEN: usize = 400;
+const ESTIMATE_INTERVAL: Duration = Duration::from_millis(500);
+
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 180.0;
+/// Tempi near this are preferred, which resolves most half/double tempo ambiguities.
+const PREFERRED_BPM: f32 = 120.0;
+/// Width of the tempo prior in octaves.
+const PRIOR_WIDTH: f32 = 1.0;
+
+/// Small changes are smoothed, bigger ones need two estimates in a row to be accepted.
+const TEMPO_TOLERANCE: f32 = 0.06;
+const TEMPO_SMOOTHING: f32 = 0.2;
+const CONFIDENCE_SMOOTHING: f32 = 0.3;
+
+/// Tempo as estimated by the tracker.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Tempo {
+    /// Beats per minute, 0 if there is no estimate yet.
+    pub bpm: f32,
+    /// Position inside the current beat, `0.0..1.0`, 0 is on the beat.
+    pub phase: f32,
+    /// How periodic the onsets are, `0.0..=1.0`.
+    pub confidence: f32,
+}
+
+/// Tempo tracker based on the autocorrelation of a spectral flux onset envelope.
+pub struct TempoTracker {
+    previous_spectrum: Vec<f32>,
+    envelope: VecDeque<f32>,
+    /// Start of the hop which is currently accumulated.
+    hop_start: Option<Instant>,
+    hop_sum: f32,
+    hop_count: u32,
+    last_hop_value: f32,
+    last_estimate: Option<Instant>,
+
+    bpm: f32,
+    pending_bpm: Option<f32>,
+    confidence: f32,
+    last_beat: Option<Instant>,
+    next_beat: Option<Instant>,
+}
+
+impl Default for TempoTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TempoTracker {
+    pub fn new() -> Self {
+        Self {
+            previous_spectrum: vec![],
+            envelope: VecDeque::with_capacity(HISTORY_LEN),
+            hop_start: None,
+            hop_sum: 0.0,
+            hop_count: 0,
+            last_hop_value: 0.0,
+            last_estimate: None,
+            bpm: 0.0,
+            pending_bpm: None,
+            confidence: 0.0,
+            last_beat: None,
+            next_beat: None,
+        }
+    }
+
+    /// Feeds a spectrum (volume per frequency bin) taken at `now`.
+    /// Returns true if a beat of the tracked tempo happened since the last call.
+    pub fn process(&mut self, now: Instant, spectrum: &[f32]) -> bool {
+        // The loop polls faster than the stream refreshes, repeated spectra carry no information.
+        if spectrum != self.previous_spectrum.as_slice() {
+            let flux = self.spectral_flux(spectrum);
+            self.push_onset(now, flux);
+        }
+
+        if self.envelope.len() >= MIN_HISTORY_LEN
+            && self
+                .last_estimate
+                .map_or(true, |last| now.duration_since(last) >= ESTIMATE_INTERVAL)
+        {
+            self.last_estimate = Some(now);
+            self.estimate(now);
+        }
+
+        self.advance_beat(now)
+    }
+
+    pub fn tempo(&self, now: Instant) -> Tempo {
+        let phase = match (self.period(), self.next_beat) {
+            (Some(period), Some(next_beat)) if next_beat > now => {
+                let remaining = next_beat.duration_since(now).as_secs_f32();
+                (1.0 - remaining / period.as_secs_f32()).clamp(0.0, 0.999)
+            }
+            _ => 0.0,
+        };
+
+        Tempo {
+            bpm: self.bpm,
+            phase,
+            confidence: self.confidence,
+        }
+    }
+
+    fn period(&self) -> Option<Duration> {
+        (self.bpm > 0.0).then(|| Duration::from_secs_f32(60.0 / self.bpm))
+    }
+
+    /// Sum of the (log compressed) increases over all bins.
+    fn spectral_flux(&mut self, spectrum: &[f32]) -> f32 {
+        let flux = if self.previous_spectrum.len() == spectrum.len() {
+            spectrum
+                .iter()
+                .zip(&self.previous_spectrum)
+                .map(|(current, previous)| {
+                    (current.max(0.0).ln_1p() - previous.max(0.0).ln_1p()).max(0.0)
+                })
+                .sum()
+        } else {
+            0.0
+        };
+
+        self.previous_spectrum.clear();
+        self.previous_spectrum.extend_from_slice(spectrum);
+        flux
+    }
+
+    fn push_onset(&mut self, now: Instant, flux: f32) {
+        let hop_start = *self.hop_start.get_or_insert(now);
+
+        // After a long pause (e.g. sleep mode) the old envelope is useless.
+        if now.duration_since(hop_start) > HOP * HISTORY_LEN as u32 {
+            self.envelope.clear();
+            self.hop_start = Some(now);
+            self.hop_sum = flux;
+            self.hop_count = 1;
+            return;
+        }
+
+        let mut hop_start = hop_start;
+        while now >= hop_start + HOP {
+            if self.hop_count > 0 {
+                self.last_hop_value = self.hop_sum / self.hop_count as f32;
+            }
+            if self.envelope.len() >= HISTORY_LEN {
+                self.envelope.pop_front();
+            }
+            self.envelope.push_back(self.last_hop_value);
+            self.hop_sum = 0.0;
+            self.hop_count = 0;
+            hop_start += HOP;
+        }
+
+        self.hop_start = Some(hop_start);
+        self.hop_sum += flux;
+        self.hop_count += 1;
+    }
+
+    fn estimate(&mut self, now: Instant) {
+        let envelope: Vec<f32> = self.envelope.iter().copied().collect();
+        let n = envelope.len();
+        let mean = envelope.iter().sum::<f32>() / n as f32;
+        let detrended: Vec<f32> = envelope.iter().map(|v| v - mean).collect();
+
+        let energy = detrended.iter().map(|v| v * v).sum::<f32>() / n as f32;
+        if energy <= f32::EPSILON {
+            self.confidence *= 1.0 - CONFIDENCE_SMOOTHING;
+            return;
+        }
+
+        let min_lag = (60.0 * ENVELOPE_RATE / MAX_BPM).floor() as usize;
+        let max_lag = (60.0 * ENVELOPE_RATE / MIN_BPM).ceil() as usize;
+
+        let autocorrelation = |lag: usize| {
+            detrended[..n - lag]
+                .iter()
+                .zip(&detrended[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (n - lag) as f32
+                / energy
+        };
+
+        // Neighbours are needed for the interpolation.
+        let acf: Vec<f32> = (min_lag - 1..=max_lag + 1).map(autocorrelation).collect();
+
+        let prior = |lag: usize| {
+            let bpm = 60.0 * ENVELOPE_RATE / lag as f32;
+            let octaves = (bpm / PREFERRED_BPM).log2() / PRIOR_WIDTH;
+            (-0.5 * octaves * octaves).exp()
+        };
+
+        let Some(best) = (1..acf.len() - 1).max_by(|a, b| {
+            let a = acf[*a] * prior(*a + min_lag - 1);
+            let b = acf[*b] * prior(*b + min_lag - 1);
+            a.total_cmp(&b)
+        }) else {
+            return;
+        };
+
+        // Parabolic interpolation around the peak for sub-hop resolution.
+        let (left, peak, right) = (acf[best - 1], acf[best], acf[best + 1]);
+        let curvature = left - 2.0 * peak + right;
+        let shift = if curvature < 0.0 {
+            (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let lag = (best + min_lag - 1) as f32 + shift;
+        let bpm = 60.0 * ENVELOPE_RATE / lag;
+
+        self.update_bpm(bpm);
+        self.confidence += (peak.clamp(0.0, 1.0) - self.confidence) * CONFIDENCE_SMOOTHING;
+        self.align_phase(now, &envelope);
+    }
+
+    fn update_bpm(&mut self, bpm: f32) {
+        let close = |a: f32, b: f32| (a - b).abs() / b < TEMPO_TOLERANCE;
+
+        if self.bpm <= 0.0 {
+            self.bpm = bpm;
+        } else if close(bpm, self.bpm) {
+            self.bpm += (bpm - self.bpm) * TEMPO_SMOOTHING;
+            self.pending_bpm = None;
+        } else if self.pending_bpm.is_some_and(|pending| close(bpm, pending)) {
+            self.bpm = bpm;
+            self.pending_bpm = None;
+        } else {
+            self.pending_bpm = Some(bpm);
+        }
+    }
+
+    /// Finds the offset of the beat grid which lines up with the most onsets.
+    fn align_phase(&mut self, now: Instant, envelope: &[f32]) {
+        let Some(period) = self.period() else {
+            return;
+        };
+
+        let period_hops = period.as_secs_f32() * ENVELOPE_RATE;
+        let last = envelope.len() - 1;
+
+        let score = |offset: usize| {
+            (0..)
+                .map(|k| offset + (k as f32 * period_hops).round() as usize)
+                .take_while(|i| *i <= last)
+                .map(|i| envelope[last - i])
+                .sum::<f32>()
+        };
+
+        let Some(offset) =
+            (0..period_hops.round() as usize).max_by(|a, b| score(*a).total_cmp(&score(*b)))
+        else {
+            return;
+        };
+
+        // The newest envelope value belongs to the hop which ended just before `now`.
+        let mut next_beat = now.checked_sub(HOP * offset as u32).unwrap_or(now);
+        let earliest = match self.last_beat {
+            Some(last_beat) => now.max(last_beat + period / 2),
+            None => now,
+        };
+        while next_beat <= earliest {
+            next_beat += period;
+        }
+
+        self.next_beat = Some(next_beat);
+    }
+
+    fn advance_beat(&mut self, now: Instant) -> bool {
+        let (Some(period), Some(next_beat)) = (self.period(), self.next_beat) else {
+            return false;
+        };
+
+        if now < next_beat {
+            return false;
+        }
+
+        let mut next_beat = next_beat;
+        while next_beat <= now {
+            next_beat += period;
+        }
+
+        self.last_beat = Some(now);
+        self.next_beat = Some(next_beat);
+        true
+    }
+}
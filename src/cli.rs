@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use cpal::{traits::DeviceTrait, Device, HostId};
+use crossbeam_channel::{select, Receiver};
+use log::{error, info, warn, LevelFilter};
+
+use crate::{
+    audio::{Signal, SystemMessage},
+    config::Config,
+    dmx::{
+        self,
+        universe::{OutputConfig, UniverseId},
+    },
+    remote::AudioDevice,
+    utils,
+};
+
+/// Audio reactive DMX lighting.
+///
+/// Starts the GUI unless `--headless` is given, a headless instance is controlled over OSC and
+/// the remote API (see the `osc` and `remote` sections of the config).
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// The config file, it is created with defaults if it does not exist.
+    /// Defaults to `$BLAULICHT_CONFIG` or `$XDG_CONFIG_HOME/blaulicht/config.toml`.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Overrides `RUST_LOG`, e.g. `debug` or `warn`.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Runs the analysis and the DMX output without the GUI.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// The audio input to analyze, by name or by its index in `list-devices`.
+    #[arg(long, value_name = "DEVICE")]
+    pub audio: Option<String>,
+
+    /// The output of a universe, by name or by its index in `list-ports`, `none` or `auto`.
+    /// Without a universe the first configured one is set. Can be given once per universe.
+    #[arg(long, value_name = "[UNIVERSE=]OUTPUT")]
+    pub dmx: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Lists the audio inputs.
+    ListDevices,
+    /// Lists the serial ports, Art-Net nodes and sACN universes which can be used as outputs.
+    ListPorts,
+}
+
+pub fn list_devices() {
+    for (i, (host, device)) in utils::get_input_devices_flat().iter().enumerate() {
+        println!(
+            "{i}: {} ({})",
+            device.name().unwrap_or_default(),
+            host.name()
+        );
+    }
+}
+
+pub fn list_ports(config: &Config) {
+    for (i, output) in dmx::available_outputs(config).iter().enumerate() {
+        match output {
+            OutputConfig::Serial {
+                driver: Some(driver),
+                ..
+            } => println!("{i}: {output} ({driver:?})"),
+            output => println!("{i}: {output}"),
+        }
+    }
+}
+
+fn find_audio_device(query: &str) -> Result<(HostId, Device)> {
+    let mut devices = utils::get_input_devices_flat();
+
+    let index = match query.parse::<usize>() {
+        Ok(index) if index < devices.len() => Some(index),
+        Ok(index) => bail!("There is no audio device {index}, see `list-devices`"),
+        Err(_) => devices
+            .iter()
+            .position(|(_, device)| device.name().is_ok_and(|name| name == query)),
+    };
+
+    let index = index.with_context(|| format!("There is no audio device `{query}`"))?;
+    Ok(devices.swap_remove(index))
+}
+
+fn find_output(outputs: &[OutputConfig], query: &str) -> Result<OutputConfig> {
+    if let Ok(index) = query.parse::<usize>() {
+        return outputs
+            .get(index)
+            .cloned()
+            .with_context(|| format!("There is no output {index}, see `list-ports`"));
+    }
+
+    match query {
+        "none" => return Ok(OutputConfig::Dummy),
+        "auto" => return Ok(OutputConfig::Auto),
+        _ => {}
+    }
+
+    outputs
+        .iter()
+        .find(|output| match output {
+            OutputConfig::Serial { port, .. } => port == query,
+            OutputConfig::ArtNet(node) => node.name == query,
+            OutputConfig::Sacn(universe) => universe.universe.to_string() == query,
+            OutputConfig::Dummy | OutputConfig::Auto => false,
+        })
+        .cloned()
+        .with_context(|| format!("There is no output `{query}`, see `list-ports`"))
+}
+
+/// Replaces the configured audio input, or file, with the one given as `--audio`. Like `--dmx`
+/// it is not stored in the config file.
+pub fn override_audio_device(config: &mut Config, query: &str) -> Result<()> {
+    let (host, device) = find_audio_device(query)?;
+
+    info!("[audio] Using {}", device.name().unwrap_or_default());
+    config.audio_file = None;
+    config.audio_device = Some(AudioDevice::new(host, &device));
+    Ok(())
+}
+
+/// Replaces the configured outputs with the ones given as `--dmx`.
+pub fn override_outputs(config: &mut Config, overrides: &[String]) -> Result<()> {
+    if overrides.is_empty() {
+        return Ok(());
+    }
+
+    let outputs = dmx::available_outputs(config);
+    for arg in overrides {
+        let (universe, query) = match arg.split_once('=') {
+            Some((universe, query)) => {
+                let universe: UniverseId = universe
+                    .parse()
+                    .with_context(|| format!("Invalid universe in `--dmx {arg}`"))?;
+                (Some(universe), query)
+            }
+            None => (None, arg.as_str()),
+        };
+
+        let output = find_output(&outputs, query)?;
+        let universe = match universe {
+            Some(id) => config.universes.iter_mut().find(|u| u.id == id),
+            None => config.universes.first_mut(),
+        }
+        .with_context(|| format!("`--dmx {arg}`: the universe is not configured"))?;
+
+        info!("[DMX] Universe {}: using {output}", universe.id);
+        universe.output = output;
+    }
+
+    Ok(())
+}
+
+/// Runs until Ctrl-C or SIGTERM. Nothing is drawn, so the signals are dropped and the
+/// system messages are logged.
+pub fn run_headless(
+    config: &Config,
+    signal_in: Receiver<Signal>,
+    sys_out: Receiver<SystemMessage>,
+) -> Result<()> {
+    if config.osc.is_none() && config.remote.is_none() {
+        warn!("Neither `osc` nor `remote` is configured, the show cannot be controlled");
+    }
+
+    let (stop_out, stop) = crossbeam_channel::bounded(1);
+    ctrlc::set_handler(move || {
+        stop_out.try_send(()).ok();
+    })
+    .context("Failed to install the signal handler")?;
+
+    info!("Running headless, stop with Ctrl-C");
+    loop {
+        select! {
+            recv(stop) -> _ => break,
+            recv(signal_in) -> signal => if signal.is_err() { break },
+            recv(sys_out) -> message => match message {
+                Ok(SystemMessage::Log(message)) => info!("{message}"),
+                Ok(SystemMessage::Error(error)) => error!("{error}"),
+                // Failures are logged by the supervisor.
+                Ok(SystemMessage::Worker(_, _)) => {}
+                Ok(SystemMessage::AudioSelected(Some(device))) => {
+                    info!("[audio] Using {}", device.name().unwrap_or_default());
+                }
+                Ok(SystemMessage::OutputSelected(universe, output)) => {
+                    info!("[DMX] Universe {universe}: {output}");
+                }
+                Ok(SystemMessage::OutputState(universe, state)) => {
+                    info!("[DMX] Universe {universe}: {state:?}");
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            },
+        }
+    }
+
+    info!("Stopping...");
+    Ok(())
+}
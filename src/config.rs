@@ -1,31 +1,325 @@
 use std::{
+    env,
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
 };
 
-use anyhow::{Context, Result};
-use log::debug;
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item};
 
-#[derive(Serialize, Deserialize)]
+use crate::dmx::{
+    artnet::ArtNetNode,
+    sacn::{Cid, SacnSource, SacnUniverse},
+    universe::{self, OutputConfig, UniverseConfig, UniverseId},
+};
+use crate::{
+    audio::{
+        agc::AgcConfig,
+        bands::{self, BandConfig},
+        file::FileInput,
+    },
+    effect::{self, EffectEntry, Scene},
+    events::{self, EventTarget},
+    fixture::patch::FixtureConfig,
+    osc::OscConfig,
+    remote::{AudioDevice, RemoteConfig},
+};
+
+/// A full DMX512 frame takes about 22.7ms on the wire, faster refresh rates are not possible.
+const MAX_DMX_REFRESH_RATE: f32 = 44.0;
+
+/// Overrides the location of the config file, unless `--config` is given.
+pub const CONFIG_ENV_VAR: &str = "BLAULICHT_CONFIG";
+
+/// Held while the config file is edited.
+static EDIT_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// Where the config was read from, selections made at runtime are stored there.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
     pub extra_serial_paths: Vec<PathBuf>,
+    /// How often the DMX output thread transmits a frame, in Hz.
+    #[serde(default = "default_dmx_refresh_rate")]
+    pub dmx_refresh_rate: f32,
+    /// DMX universes and the output each of them is sent to.
+    #[serde(default = "UniverseConfig::default_universes")]
+    pub universes: Vec<UniverseConfig>,
+    /// Fixture instances, checked against the fixture library on startup.
+    #[serde(default = "FixtureConfig::default_patch")]
+    pub patch: Vec<FixtureConfig>,
+    /// Frequency bands which are analyzed and published as band signals.
+    #[serde(default = "BandConfig::default_bands")]
+    pub bands: Vec<BandConfig>,
+    /// Gain control which normalizes the input before it is analyzed.
+    #[serde(default)]
+    pub agc: AgcConfig,
+    /// Analyzes this file instead of a live input device, e.g. to rehearse a show.
+    #[serde(default)]
+    pub audio_file: Option<FileInput>,
+    /// The input which was selected last, it is selected again on startup.
+    #[serde(default)]
+    pub audio_device: Option<AudioDevice>,
+    /// Beats per bar of the beat clock.
+    #[serde(default = "default_beats_per_bar")]
+    pub beats_per_bar: u8,
+    /// The effect stack, later effects win where they control the same channel.
+    #[serde(default = "EffectEntry::default_effects")]
+    pub effects: Vec<EffectEntry>,
+    /// Effect stacks which can be switched to during the show, e.g. over OSC.
+    #[serde(default)]
+    pub scenes: Vec<Scene>,
+    /// Receivers of beat, tempo, band, drop and section events.
+    #[serde(default)]
+    pub event_targets: Vec<EventTarget>,
+    /// Listens for OSC commands if set.
+    #[serde(default)]
+    pub osc: Option<OscConfig>,
+    /// Serves the HTTP and WebSocket API for remote controls if set.
+    #[serde(default)]
+    pub remote: Option<RemoteConfig>,
+    /// Art-Net nodes which are offered as DMX outputs in addition to discovered ones.
+    #[serde(default)]
+    pub artnet_nodes: Vec<ArtNetNode>,
+    /// How this instance identifies itself to sACN receivers.
+    #[serde(default)]
+    pub sacn_source: SacnSource,
+    /// sACN universes which are offered as DMX outputs.
+    #[serde(default)]
+    pub sacn_universes: Vec<SacnUniverse>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            path: None,
             extra_serial_paths: vec!["/dev/pts/0".into()],
+            dmx_refresh_rate: default_dmx_refresh_rate(),
+            universes: UniverseConfig::default_universes(),
+            patch: FixtureConfig::default_patch(),
+            bands: BandConfig::default_bands(),
+            agc: AgcConfig::default(),
+            audio_file: None,
+            audio_device: None,
+            beats_per_bar: default_beats_per_bar(),
+            effects: EffectEntry::default_effects(),
+            scenes: vec![],
+            event_targets: vec![],
+            osc: None,
+            remote: None,
+            artnet_nodes: vec![],
+            sacn_source: SacnSource::default(),
+            sacn_universes: vec![],
+        }
+    }
+}
+
+fn default_dmx_refresh_rate() -> f32 {
+    40.0
+}
+
+fn default_beats_per_bar() -> u8 {
+    4
+}
+
+/// A value as a TOML table, e.g. to replace a table of the config file.
+fn to_item(value: &impl Serialize) -> Result<Item> {
+    let document = toml_edit::ser::to_document(value)?;
+    Ok(Item::Table(document.as_table().clone()))
+}
+
+impl Config {
+    /// Edits the config file in place, the rest of it including comments is kept.
+    fn edit_file(&self, edit: impl FnOnce(&mut DocumentMut) -> Result<()>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // Several threads store their selections, one edit must not read the file while another
+        // one writes it.
+        let _lock = EDIT_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read `{}`", path.display()))?;
+        let mut document: DocumentMut = content
+            .parse()
+            .with_context(|| format!("Invalid config file `{}`", path.display()))?;
+        edit(&mut document)?;
+
+        // Replaced at once, a crash while writing leaves the old file.
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+        fs::write(&temp_path, document.to_string())
+            .with_context(|| format!("Failed to write `{}`", temp_path.display()))?;
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace `{}`", path.display()))
+    }
+
+    pub fn store_audio_device(&self, device: Option<&AudioDevice>) -> Result<()> {
+        self.edit_file(|document| {
+            match device {
+                Some(device) => document["audio_device"] = to_item(device)?,
+                None => {
+                    document.remove("audio_device");
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// The config as it is in the file now, with the selections made since it was read.
+    /// Without a file, or if it cannot be read, this config is returned.
+    pub fn reread(&self) -> Config {
+        let Some(path) = &self.path else {
+            return self.clone();
+        };
+
+        let config = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(toml::from_str::<Config>(&content)?));
+        match config {
+            Ok(config) => Config {
+                path: self.path.clone(),
+                ..config
+            },
+            Err(err) => {
+                warn!("Failed to read `{}` again: {err:#}", path.display());
+                self.clone()
+            }
         }
     }
+
+    /// Keeps the gain of a "learn room" calibration, it is used again when the analysis restarts.
+    pub fn store_agc_gain(&self, gain: f32) -> Result<()> {
+        self.edit_file(|document| {
+            match document.get_mut("agc") {
+                Some(agc) => agc["calibrated_gain"] = toml_edit::value(gain as f64),
+                None => {
+                    let agc = AgcConfig {
+                        calibrated_gain: Some(gain),
+                        ..self.agc.clone()
+                    };
+                    document["agc"] = to_item(&agc)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// The calibrated AGC gain in the config file, it is newer than `self.agc` after a
+    /// calibration.
+    pub fn stored_agc_gain(&self) -> Option<f32> {
+        let path = self.path.as_ref()?;
+        let document: DocumentMut = fs::read_to_string(path).ok()?.parse().ok()?;
+        let gain = document.get("agc")?.get("calibrated_gain")?;
+        gain.as_float()
+            .or_else(|| gain.as_integer().map(|gain| gain as f64))
+            .map(|gain| gain as f32)
+    }
+
+    /// Keeps a generated sACN CID, receivers identify a source by it across restarts.
+    fn store_sacn_cid(&self) -> Result<()> {
+        let Some(cid) = self.sacn_source.cid else {
+            return Ok(());
+        };
+
+        self.edit_file(|document| {
+            match document.get_mut("sacn_source") {
+                Some(source) => source["cid"] = toml_edit::value(cid.to_string()),
+                None => document["sacn_source"] = to_item(&self.sacn_source)?,
+            }
+            Ok(())
+        })
+    }
+
+    pub fn store_output(&self, universe: UniverseId, output: &OutputConfig) -> Result<()> {
+        self.edit_file(|document| {
+            let stored = document
+                .get_mut("universes")
+                .and_then(Item::as_array_of_tables_mut)
+                .and_then(|universes| {
+                    universes.iter_mut().find(|table| {
+                        table.get("id").and_then(Item::as_integer) == Some(universe.into())
+                    })
+                });
+            if let Some(table) = stored {
+                table["output"] = to_item(output)?;
+                return Ok(());
+            }
+
+            // The universes are not in the file yet (or written inline), all of them are written.
+            // They are taken from the file, `self.universes` has the `--dmx` overrides applied.
+            #[derive(Serialize, Deserialize)]
+            struct Universes {
+                #[serde(default = "UniverseConfig::default_universes")]
+                universes: Vec<UniverseConfig>,
+            }
+
+            let Universes { mut universes } = toml::from_str(&document.to_string())?;
+            if let Some(config) = universes.iter_mut().find(|u| u.id == universe) {
+                config.output = output.clone();
+            }
+            let universes =
+                toml_edit::ser::to_document(&Universes { universes })?["universes"].clone();
+            document["universes"] = universes
+                .into_array_of_tables()
+                .map_or_else(|item| item, Item::ArrayOfTables);
+            Ok(())
+        })
+    }
+}
+
+/// The config file: `--config`, then `$BLAULICHT_CONFIG`, then `blaulicht/config.toml` in the
+/// user's config directory (`$XDG_CONFIG_HOME`, `~/.config` or `%APPDATA%`).
+pub fn config_path(cli_path: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(path) = cli_path {
+        return Ok(path);
+    }
+
+    if let Some(path) = env::var_os(CONFIG_ENV_VAR).filter(|path| !path.is_empty()) {
+        return Ok(path.into());
+    }
+
+    Ok(config_dir()?.join("blaulicht").join("config.toml"))
+}
+
+fn config_dir() -> Result<PathBuf> {
+    // Relative paths are invalid according to the XDG base directory spec.
+    let absolute = |var: &str| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+
+    if let Some(dir) = absolute("XDG_CONFIG_HOME") {
+        return Ok(dir);
+    }
+    if let Some(home) = absolute("HOME") {
+        return Ok(home.join(".config"));
+    }
+    if let Some(dir) = absolute("APPDATA") {
+        return Ok(dir);
+    }
+
+    bail!("Cannot find the config directory, set `{CONFIG_ENV_VAR}` or use `--config`")
 }
 
-pub fn config_path() -> Result<PathBuf> {
-    Ok("~/blualicht.toml".into())
+/// User fixture profiles live in a `fixtures` directory next to the config file.
+pub fn fixtures_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("fixtures")
 }
 
-pub fn read_config(file_path: PathBuf) -> Result<Option<Config>> {
+/// Reads the config file, a missing one is created with the defaults which are then used.
+pub fn read_config(file_path: PathBuf) -> Result<Config> {
     // Either read or create a configuration file based on it's current existence
     let path = Path::new(&file_path);
     match &path.exists() {
@@ -35,21 +329,63 @@ pub fn read_config(file_path: PathBuf) -> Result<Option<Config>> {
                 "Found existing config file at {}",
                 file_path.to_string_lossy()
             );
-            let content = fs::read_to_string(path)?;
-            let config = toml::from_str(&content)?;
-            Ok(Some(config))
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read `{}`", path.display()))?;
+            let mut config: Config = toml::from_str(&content)
+                .with_context(|| format!("Invalid config file `{}`", path.display()))?;
+            config.path = Some(file_path.clone());
+
+            if !(1.0..=MAX_DMX_REFRESH_RATE).contains(&config.dmx_refresh_rate) {
+                bail!(
+                    "`dmx_refresh_rate` must be between 1 and {MAX_DMX_REFRESH_RATE} Hz, got {}",
+                    config.dmx_refresh_rate
+                );
+            }
+
+            if config.beats_per_bar == 0 {
+                bail!("`beats_per_bar` must be at least 1");
+            }
+
+            universe::validate_universes(&config.universes)?;
+            bands::validate_bands(&config.bands)?;
+            config.agc.validate()?;
+            events::validate_targets(&config.event_targets)?;
+            effect::validate_scenes(&config.scenes)?;
+            if let Some(remote) = &config.remote {
+                remote.validate()?;
+            }
+
+            if config.sacn_source.cid.is_none() {
+                config.sacn_source.cid = Some(Cid::generate());
+                if let Err(err) = config.store_sacn_cid() {
+                    warn!("Failed to store the sACN CID: {err:#}");
+                }
+            }
+
+            Ok(config)
         }
         false => {
             // The file does not exist, therefore create a new one
-            fs::create_dir_all(path.parent().unwrap())?;
-            let mut file = File::create(path)?;
-            file.write_all(
-                toml::to_string_pretty(&Config::default())
-                    .unwrap()
-                    .as_bytes(),
-            )
-            .with_context(|| "Failed to write default config file (create new one)")?;
-            Ok(None)
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+            }
+            let mut config = Config {
+                path: Some(file_path.clone()),
+                ..Config::default()
+            };
+            config.sacn_source.cid = Some(Cid::generate());
+
+            let mut file = File::create(path)
+                .with_context(|| format!("Failed to create `{}`", path.display()))?;
+            file.write_all(toml::to_string_pretty(&config).unwrap().as_bytes())
+                .with_context(|| "Failed to write default config file (create new one)")?;
+
+            info!("Created default config file at `{}`", path.display());
+            Ok(config)
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
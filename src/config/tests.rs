@@ -0,0 +1,60 @@
+use std::{env, fs, process, thread};
+
+use super::read_config;
+use crate::{dmx::universe::OutputConfig, remote::AudioDevice};
+
+#[test]
+fn concurrent_edits_keep_the_whole_file() {
+    let dir = env::temp_dir().join(format!("blaulicht-config-{}", process::id()));
+    let path = dir.join("config.toml");
+    let config = read_config(path.clone()).unwrap();
+
+    let device = AudioDevice {
+        host: "ALSA".to_string(),
+        name: "Line In".to_string(),
+    };
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for _ in 0..50 {
+                config.store_audio_device(Some(&device)).unwrap();
+            }
+        });
+        scope.spawn(|| {
+            for _ in 0..50 {
+                config.store_output(1, &OutputConfig::Dummy).unwrap();
+            }
+        });
+    });
+
+    let stored = read_config(path.clone());
+    let temp_exists = dir.join("config.toml.tmp").exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stored = stored.unwrap();
+    assert_eq!(stored.audio_device, Some(device));
+    assert_eq!(stored.universes[0].output, OutputConfig::Dummy);
+    assert_eq!(stored.sacn_source.cid, config.sacn_source.cid);
+    assert!(!temp_exists);
+}
+
+#[test]
+fn calibrated_gains_are_stored() {
+    let dir = env::temp_dir().join(format!("blaulicht-agc-{}", process::id()));
+    let path = dir.join("config.toml");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&path, "extra_serial_paths = []\n").unwrap();
+    let config = read_config(path.clone()).unwrap();
+
+    let missing = config.stored_agc_gain();
+    config.store_agc_gain(2.5).unwrap();
+    let stored = config.stored_agc_gain();
+    config.store_agc_gain(0.5).unwrap();
+    let replaced = config.stored_agc_gain();
+    let reread = read_config(path.clone());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(missing, None);
+    assert_eq!(stored, Some(2.5));
+    assert_eq!(replaced, Some(0.5));
+    assert_eq!(reread.unwrap().agc.calibrated_gain, Some(0.5));
+}
@@ -1,407 +1,927 @@
 use std::{
-    net::UdpSocket,
-    sync::{
-        atomic::{AtomicU8, Ordering},
-        Arc,
-    },
+    collections::{BTreeMap, BTreeSet},
+    sync::atomic::{AtomicU8, Ordering},
     thread,
     time::{Duration, Instant},
 };
 
-use cpal::{traits::DeviceTrait, Device};
+use anyhow::{bail, Context};
+use cpal::Device;
 use crossbeam_channel::{Receiver, Sender, TryRecvError};
-use log::{info, warn};
-use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPort, SerialPortInfo, SerialPortType, UsbPortInfo};
 
 use crate::{
     app::FromFrontend,
-    audio::{self, AudioThreadControlSignal, Signal, SystemMessage},
+    audio::{self, AnalysisContext, AudioSource, AudioThreadControlSignal, SystemMessage},
+    config,
+    remote::AudioDevice,
+    supervisor::{self, Worker, WorkerError},
     utils,
 };
 
+pub mod artnet;
+pub mod enttec;
+pub mod sacn;
+pub mod universe;
+
+use artnet::{ArtNetNode, ArtNetOutput};
+use enttec::EnttecProOutput;
+use sacn::{SacnOutput, SacnSource, SacnUniverse};
+use universe::{DmxFrames, OutputConfig, UniverseConfig, UniverseId};
+
+/// The DMX channel buffer, slot 0 is the start code.
+pub type DmxFrame = [u8; 513];
+
+/// A DMX output, owned by the DMX output thread.
 pub enum DmxUniverse {
     Dummy,
     Real(DmxUniverseReal),
+    EnttecPro(EnttecProOutput),
+    ArtNet(ArtNetOutput),
+    Sacn(SacnOutput),
 }
 
 impl DmxUniverse {
-    pub fn new(port_path: String) -> Self {
-        Self::Real(DmxUniverseReal::new(port_path))
+    pub fn new(port_path: String) -> anyhow::Result<Self> {
+        Ok(Self::Real(DmxUniverseReal::new(port_path)?))
     }
 
-    pub fn new_dummy() -> Self {
-        Self::Dummy
+    pub fn new_serial(port_path: String, driver: SerialDriver) -> anyhow::Result<Self> {
+        match driver {
+            SerialDriver::OpenDmx => Self::new(port_path),
+            SerialDriver::EnttecPro => Ok(Self::EnttecPro(EnttecProOutput::new(&port_path)?)),
+        }
     }
 
-    pub fn signal(&mut self, signal: Signal) {
-        match self {
-            DmxUniverse::Dummy => {}
-            DmxUniverse::Real(dmx_universe_real) => dmx_universe_real.signal(signal),
-        }
+    pub fn new_artnet(node: ArtNetNode) -> anyhow::Result<Self> {
+        Ok(Self::ArtNet(ArtNetOutput::new(node)?))
     }
-}
 
-enum Color {
-    Red,
-    Purple,
-    Blue,
-    Cyan,
-    Green,
-    Yellow,
-}
+    pub fn new_sacn(source: SacnSource, universe: SacnUniverse) -> anyhow::Result<Self> {
+        Ok(Self::Sacn(SacnOutput::new(source, universe)?))
+    }
 
-impl Color {
-    fn from_index(index: u8) -> Self {
-        match index {
-            0 => Self::Red,
-            1 => Self::Purple,
-            2 => Self::Blue,
-            3 => Self::Cyan,
-            4 => Self::Green,
-            5 => Color::Yellow,
-            _ => unreachable!(),
-        }
+    pub fn new_dummy() -> Self {
+        Self::Dummy
+    }
+
+    /// Serial interfaces are closed when writing fails, they are likely unplugged.
+    fn is_serial(&self) -> bool {
+        matches!(self, DmxUniverse::Real(_) | DmxUniverse::EnttecPro(_))
     }
 
-    fn channels(&self) -> [u8; 3] {
+    pub fn write(&mut self, channels: &DmxFrame) -> anyhow::Result<()> {
         match self {
-            Color::Red => [255, 0, 0],
-            Color::Purple => [255, 0, 255],
-            Color::Blue => [0, 0, 255],
-            Color::Cyan => [0, 255, 255],
-            Color::Green => [0, 255, 0],
-            Color::Yellow => [255, 255, 0],
+            DmxUniverse::Dummy => {}
+            DmxUniverse::Real(dmx_universe_real) => dmx_universe_real.write_to_serial(channels)?,
+            DmxUniverse::EnttecPro(output) => output.write(channels)?,
+            DmxUniverse::ArtNet(output) => output.send(channels)?,
+            DmxUniverse::Sacn(output) => output.send(channels)?,
         }
+
+        Ok(())
     }
 }
 
-struct DmxUniverseReal {
+pub struct DmxUniverseReal {
     serial: Box<dyn SerialPort>,
-    channels: [u8; 513],
-    last_update: Instant,
-
-    color_idx: u8,
-    color_set_time: Instant,
-    // start_of_drop: Instant,
-    // bass_count: usize,
-    //socket: UdpSocket,
 }
 
 impl DmxUniverseReal {
-    fn new(port_path: String) -> Self {
-        let port = serialport::new(port_path, 250000)
+    fn new(port_path: String) -> anyhow::Result<Self> {
+        let port = serialport::new(&port_path, 250000)
             .timeout(Duration::from_millis(1))
             .stop_bits(serialport::StopBits::Two)
             .data_bits(serialport::DataBits::Eight)
             .parity(serialport::Parity::None)
             .open()
-            .expect("Failed to open port");
+            .with_context(|| format!("Failed to open {port_path}"))?;
+
+        Ok(Self { serial: port })
+    }
+
+    fn send_break(&self, duration: Duration) -> serialport::Result<()> {
+        self.serial.set_break()?;
+        spin_sleep::sleep(duration);
+        self.serial.clear_break()
+    }
+
+    fn write_to_serial(&mut self, channels: &DmxFrame) -> anyhow::Result<()> {
+        self.send_break(Duration::from_micros(100))?;
+        spin_sleep::sleep(Duration::from_micros(100));
+        self.serial.write_all(channels)?;
+        self.serial.flush()?;
+        Ok(())
+    }
+}
+
+/// How a serial DMX interface has to be driven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialDriver {
+    /// FTDI "open" style interfaces: break is toggled manually, slots are written raw.
+    OpenDmx,
+    /// Enttec DMX USB Pro widget protocol, framed messages.
+    EnttecPro,
+}
+
+pub struct UsbDevice {
+    pub vid: u16,
+    pub pid: u16,
+    /// USB product string, required if several interfaces share the same VID/PID.
+    pub product: Option<&'static str>,
+    pub driver: SerialDriver,
+}
+
+impl UsbDevice {
+    fn matches(&self, usb: &UsbPortInfo) -> bool {
+        self.vid == usb.vid
+            && self.pid == usb.pid
+            && self.product.map_or(true, |product| {
+                usb.product
+                    .as_ref()
+                    .is_some_and(|p| p.eq_ignore_ascii_case(product))
+            })
+    }
+}
+
+pub const ENTTEC_DMX_USB_PRO: UsbDevice = UsbDevice {
+    vid: 1027,
+    pid: 24577,
+    product: Some("DMX USB PRO"),
+    driver: SerialDriver::EnttecPro,
+};
+
+pub const EUROLITE_USB_DMX512_PRO_CABLE_INTERFACE: UsbDevice = UsbDevice {
+    vid: 1027,
+    pid: 24577,
+    product: None,
+    driver: SerialDriver::OpenDmx,
+};
+
+/// Identifies a USB interface independent of the port it is plugged into.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+    /// Tells identical interfaces apart, not every interface has one.
+    #[serde(default)]
+    pub serial_number: Option<String>,
+}
+
+impl UsbId {
+    pub fn of(port: &SerialPortInfo) -> Option<Self> {
+        let SerialPortType::UsbPort(usb) = &port.port_type else {
+            return None;
+        };
+
+        Some(Self {
+            vid: usb.vid,
+            pid: usb.pid,
+            serial_number: usb.serial_number.clone(),
+        })
+    }
+
+    fn matches(&self, port: &SerialPortInfo) -> bool {
+        Self::of(port).is_some_and(|id| {
+            id.vid == self.vid
+                && id.pid == self.pid
+                && (self.serial_number.is_none() || id.serial_number == self.serial_number)
+        })
+    }
+}
+
+/// Known interfaces, more specific entries have to come first.
+pub const USB_DEVICES: [UsbDevice; 2] =
+    [ENTTEC_DMX_USB_PRO, EUROLITE_USB_DMX512_PRO_CABLE_INTERFACE];
+/// How often the serial ports are listed, and a lost interface is looked for.
+const SERIAL_RESCAN: Duration = Duration::from_secs(1);
+
+/// Returns the known interface behind a serial port, if any.
+pub fn usb_device(port: &SerialPortInfo) -> Option<&'static UsbDevice> {
+    let SerialPortType::UsbPort(usb) = &port.port_type else {
+        return None;
+    };
+
+    USB_DEVICES.iter().find(|d| d.matches(usb))
+}
+
+/// Whether the frames of a universe reach its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputState {
+    Connected,
+    /// Writing failed. Network outputs keep sending, serial interfaces are opened again once
+    /// they are plugged back in.
+    Disconnected,
+}
+
+pub enum DMXControl {
+    /// Switches the output of a universe, the old output is closed first.
+    ChangePort(UniverseId, OutputConfig),
+    /// Stops the DMX thread after the output has been shut down cleanly.
+    Shutdown,
+}
+
+const ARTNET_DISCOVERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Everything that is known about the available outputs when the universes are opened.
+struct OutputCandidates<'a> {
+    config: &'a config::Config,
+    ports: Vec<SerialPortInfo>,
+    artnet_nodes: Vec<ArtNetNode>,
+    /// Outputs which are used by a universe and cannot be picked by `Auto`.
+    in_use: BTreeMap<UniverseId, OutputConfig>,
+}
+
+fn serial_ports() -> Vec<SerialPortInfo> {
+    serialport::available_ports().unwrap_or_else(|err| {
+        warn!("[DMX] Failed to list serial ports: {err}");
+        vec![]
+    })
+}
+
+impl<'a> OutputCandidates<'a> {
+    /// Lists the serial ports and asks the network for Art-Net nodes.
+    fn scan(config: &'a config::Config) -> Self {
+        let ports = serial_ports();
+
+        // Art-Net nodes: configured ones first, then whatever answers an ArtPoll.
+        let mut artnet_nodes = config.artnet_nodes.clone();
+        match artnet::discover(ARTNET_DISCOVERY_TIMEOUT) {
+            Ok(replies) => {
+                for node in replies.iter().map(|reply| reply.node()) {
+                    if !artnet_nodes.iter().any(|n| n.address == node.address) {
+                        artnet_nodes.push(node);
+                    }
+                }
+            }
+            Err(err) => warn!("[DMX] Art-Net discovery failed: {err}"),
+        }
 
         Self {
-            serial: port,
-            channels: [0; 513],
-            last_update: Instant::now(),
-            color_idx: 0,
-            color_set_time: Instant::now(),
+            config,
+            ports,
+            artnet_nodes,
+            in_use: BTreeMap::new(),
         }
     }
 
-    fn signal(&mut self, signal: Signal) {
-        match signal {
-            Signal::BeatVolume(volume) => {
-                // TODO: engine here
-                // return;
-                if volume >= 1 {
-                    println!("V={volume}");
-                    // self.channels[1] = volume / 10;
-
-                    if self.color_set_time.elapsed().as_secs() > 10 {
-                        self.color_set_time = Instant::now();
-                        if self.color_idx == 5 {
-                            self.color_idx = 0;
-                        } else {
-                            self.color_idx += 1;
-                        }
-                    }
+    /// Takes the serial ports from [`scan_serial_ports`], interfaces may have been plugged in
+    /// since the start.
+    fn update_ports(&mut self, ports: Vec<SerialPortInfo>, system_out: &Sender<SystemMessage>) {
+        if ports == self.ports {
+            return;
+        }
 
-                    let c = Color::from_index(self.color_idx).channels();
-                    self.channels[1] = 255;
-                    self.channels[2] = c[0];
-                    self.channels[3] = c[1];
-                    self.channels[4] = c[2];
-                    self.write_to_serial();
-                } else {
-                    self.channels[1] = 0;
-                    self.write_to_serial();
-                }
+        self.ports = ports;
+        system_out
+            .send(SystemMessage::SerialDevicesView(self.ports.clone()))
+            .ok();
+    }
 
-                // spin_sleep::sleep(Duration::from_millis(10));
-            }
-            Signal::BeatAlgo(_) => {
-                // return;
-                self.channels[1] = 255;
-                self.channels[2] = 255;
-                self.channels[3] = 255;
-                self.channels[4] = 255;
+    fn is_in_use(&self, output: &OutputConfig) -> bool {
+        self.in_use.values().any(|used| used.same_output(output))
+    }
+
+    /// Whether a universe other than `id` has opened the serial port.
+    fn port_used_by_other(&self, id: UniverseId, port: &str) -> bool {
+        self.in_use.iter().any(|(other, used)| {
+            *other != id
+                && matches!(used, OutputConfig::Serial { port: used_port, .. } if used_port == port)
+        })
+    }
 
-                self.write_to_serial();
-                spin_sleep::sleep(Duration::from_millis(10));
+    fn discover(config: &'a config::Config, system_out: &Sender<SystemMessage>) -> Self {
+        let candidates = Self::scan(config);
 
-                self.channels[1] = 0;
+        // Update available ports to frontend.
+        system_out
+            .send(SystemMessage::SerialDevicesView(candidates.ports.clone()))
+            .ok();
+        system_out
+            .send(SystemMessage::ArtNetNodesView(
+                candidates.artnet_nodes.clone(),
+            ))
+            .ok();
 
-                self.write_to_serial();
+        candidates
+    }
+
+    /// Every output which can be picked, serial ports first.
+    fn outputs(&self) -> Vec<OutputConfig> {
+        let serial = self.ports.iter().map(|p| OutputConfig::Serial {
+            port: p.port_name.clone(),
+            driver: usb_device(p).map(|device| device.driver),
+            usb: UsbId::of(p),
+        });
+        let artnet = self.artnet_nodes.iter().cloned().map(OutputConfig::ArtNet);
+        let sacn = self
+            .config
+            .sacn_universes
+            .iter()
+            .cloned()
+            .map(OutputConfig::Sacn);
+
+        serial.chain(artnet).chain(sacn).collect()
+    }
+
+    /// Resolves `Auto`: a known USB interface, then Art-Net, then sACN.
+    fn resolve_auto(&self) -> OutputConfig {
+        let serial_port = self.ports.iter().find_map(|p| {
+            let device = usb_device(p)?;
+            let output = OutputConfig::Serial {
+                port: p.port_name.clone(),
+                driver: Some(device.driver),
+                usb: UsbId::of(p),
+            };
+            (!self.is_in_use(&output)).then_some(output)
+        });
+
+        let artnet_node = self
+            .artnet_nodes
+            .iter()
+            .map(|node| OutputConfig::ArtNet(node.clone()))
+            .find(|output| !self.is_in_use(output));
+
+        let sacn_universe = self
+            .config
+            .sacn_universes
+            .iter()
+            .map(|universe| OutputConfig::Sacn(universe.clone()))
+            .find(|output| !self.is_in_use(output));
+
+        serial_port
+            .or(artnet_node)
+            .or(sacn_universe)
+            .unwrap_or(OutputConfig::Dummy)
+    }
+
+    /// Fills in the serial driver from the USB VID/PID if it was not configured.
+    fn resolve_driver(&self, port: &str) -> SerialDriver {
+        self.ports
+            .iter()
+            .find(|p| p.port_name == port)
+            .and_then(usb_device)
+            .map_or(SerialDriver::OpenDmx, |device| device.driver)
+    }
+
+    /// Opens the serial interface of universe `id` again, a USB interface may come back on
+    /// another port. Ports of other universes are skipped, a USB ID without a serial number
+    /// matches all interfaces of the same model.
+    fn reconnect(
+        &self,
+        id: UniverseId,
+        lost: &OutputConfig,
+    ) -> anyhow::Result<(DmxUniverse, OutputConfig)> {
+        let OutputConfig::Serial { port, driver, usb } = lost else {
+            bail!("{lost} is not a serial interface");
+        };
+
+        let port = match usb {
+            Some(usb) => {
+                let found = self
+                    .ports
+                    .iter()
+                    .find(|p| usb.matches(p) && !self.port_used_by_other(id, &p.port_name));
+                &found.context("The interface is not plugged in")?.port_name
             }
-            Signal::Bass(v) => {
-                if v > 20 && self.last_update.elapsed().as_millis() > 100 {
-                    const CHANNEL_OFFSET_STROBE: usize = 10;
+            // E.g. `extra_serial_paths`, which are not listed.
+            None => port,
+        };
+        if self.port_used_by_other(id, port) {
+            bail!("{port} is used by another universe");
+        }
 
-                    self.last_update = Instant::now();
-                    self.channels[1 + CHANNEL_OFFSET_STROBE - 1] = 255;
-                    self.channels[2 + CHANNEL_OFFSET_STROBE - 1] = 255;
-                    self.channels[3 + CHANNEL_OFFSET_STROBE - 1] = 255;
-                    self.channels[4 + CHANNEL_OFFSET_STROBE - 1] = 255;
-                    self.write_to_serial();
-                    spin_sleep::sleep(Duration::from_millis(1));
+        let driver = driver.unwrap_or(SerialDriver::OpenDmx);
+        let universe = DmxUniverse::new_serial(port.clone(), driver)?;
+        let output = OutputConfig::Serial {
+            port: port.clone(),
+            driver: Some(driver),
+            usb: usb.clone(),
+        };
+        Ok((universe, output))
+    }
 
-                    self.channels[1 + CHANNEL_OFFSET_STROBE - 1] = 0;
+    /// A USB interface is looked up by its ID, other serial devices by their port.
+    fn find_serial(&self, port: &str, usb: Option<&UsbId>) -> Option<&SerialPortInfo> {
+        match usb {
+            Some(usb) => self.ports.iter().find(|p| usb.matches(p)),
+            None => self.ports.iter().find(|p| p.port_name == port),
+        }
+    }
 
-                    self.write_to_serial();
+    fn open(
+        &mut self,
+        id: UniverseId,
+        output: &OutputConfig,
+        system_out: &Sender<SystemMessage>,
+    ) -> anyhow::Result<(DmxUniverse, OutputConfig)> {
+        let output = match output {
+            OutputConfig::Auto => self.resolve_auto(),
+            OutputConfig::Serial { port, driver, usb } => {
+                match self.find_serial(port, usb.as_ref()) {
+                    Some(found) => OutputConfig::Serial {
+                        port: found.port_name.clone(),
+                        driver: Some(
+                            driver.unwrap_or_else(|| self.resolve_driver(&found.port_name)),
+                        ),
+                        usb: UsbId::of(found),
+                    },
+                    // Other paths, e.g. `extra_serial_paths`, are not listed but opened anyway.
+                    None if usb.is_some() => {
+                        warn!("[DMX] {port} is not connected, picking another output");
+                        self.resolve_auto()
+                    }
+                    None => OutputConfig::Serial {
+                        port: port.clone(),
+                        driver: Some(driver.unwrap_or_else(|| self.resolve_driver(port))),
+                        usb: None,
+                    },
                 }
             }
-            Signal::Volume(v) => {
-                if v < 10 {
-                    self.channels[1] = 30;
-                    self.channels[2] = 255;
-                    self.channels[3] = 0;
-                    self.channels[4] = 255;
-
-                    self.write_to_serial();
+            output => output.clone(),
+        };
+
+        if let Some((other, _)) = self
+            .in_use
+            .iter()
+            .find(|(_, used)| used.same_output(&output))
+        {
+            bail!("{output} is already used by universe {other}");
+        }
+
+        let universe = match &output {
+            OutputConfig::Dummy | OutputConfig::Auto => DmxUniverse::new_dummy(),
+            OutputConfig::Serial { port, driver, .. } => {
+                let driver = driver.unwrap_or(SerialDriver::OpenDmx);
+                info!("[DMX] Using serial device: {port} ({driver:?})");
+                let universe = DmxUniverse::new_serial(port.clone(), driver)?;
+
+                if let DmxUniverse::EnttecPro(output) = &universe {
+                    let mut message = "[DMX] Enttec DMX USB Pro".to_string();
+                    if let Some(serial_number) = output.serial_number() {
+                        message += &format!(" #{serial_number}");
+                    }
+                    if let Some(parameters) = output.parameters() {
+                        message += &format!(
+                            ": firmware {}.{}, break {}us, MAB {}us, {} packets/s",
+                            parameters.firmware_version >> 8,
+                            parameters.firmware_version & 0xFF,
+                            parameters.break_time_us,
+                            parameters.mark_after_break_time_us,
+                            parameters.output_rate,
+                        );
+                    }
+                    system_out.send(SystemMessage::Log(message)).ok();
                 }
+
+                universe
+            }
+            OutputConfig::ArtNet(node) => {
+                info!("[DMX] Using Art-Net node: {} ({})", node.name, node.address);
+                DmxUniverse::new_artnet(node.clone())?
             }
+            OutputConfig::Sacn(universe) => {
+                info!("[DMX] Using sACN universe: {universe}");
+                DmxUniverse::new_sacn(self.config.sacn_source.clone(), universe.clone())?
+            }
+        };
+
+        if output != OutputConfig::Dummy {
+            self.in_use.insert(id, output.clone());
         }
+
+        Ok((universe, output))
     }
+}
 
-    fn send_break(&self, duration: Duration) {
-        self.serial.set_break().expect("Failed to set break");
-        spin_sleep::sleep(duration);
-        self.serial.clear_break().expect("Failed to clear break");
+/// Serial ports, Art-Net nodes and sACN universes which can be selected as outputs.
+pub fn available_outputs(config: &config::Config) -> Vec<OutputConfig> {
+    OutputCandidates::scan(config).outputs()
+}
+
+/// Opens the outputs of all configured universes, failed outputs fall back to dummies.
+fn open_universes(
+    candidates: &mut OutputCandidates<'_>,
+    system_out: &Sender<SystemMessage>,
+) -> Vec<(UniverseId, DmxUniverse)> {
+    // Explicitly configured outputs have to be claimed before `Auto` picks one.
+    let mut universes: Vec<&UniverseConfig> = candidates.config.universes.iter().collect();
+    universes.sort_by_key(|universe| universe.output == OutputConfig::Auto);
+
+    let mut opened: Vec<(UniverseId, DmxUniverse)> = universes
+        .into_iter()
+        .map(|universe_config| {
+            match candidates.open(universe_config.id, &universe_config.output, system_out) {
+                Ok((universe, output)) => {
+                    if output == OutputConfig::Dummy {
+                        warn!("[DMX] Universe {}: no output available", universe_config.id);
+                    }
+
+                    system_out
+                        .send(SystemMessage::OutputSelected(universe_config.id, output))
+                        .ok();
+                    (universe_config.id, universe)
+                }
+                Err(err) => {
+                    system_out
+                        .send(SystemMessage::Error(WorkerError::new(
+                            Worker::Dmx,
+                            format!("Universe {}: {err:#}", universe_config.id),
+                        )))
+                        .ok();
+                    system_out
+                        .send(SystemMessage::OutputSelected(
+                            universe_config.id,
+                            OutputConfig::Dummy,
+                        ))
+                        .ok();
+                    (universe_config.id, DmxUniverse::new_dummy())
+                }
+            }
+        })
+        .collect();
+
+    opened.sort_by_key(|(id, _)| *id);
+    opened
+}
+
+/// Closes the output of a universe and opens another one. The result is reported as
+/// `OutputSelected` and stored in the config file, the previous output is reopened if the new
+/// one fails.
+fn change_output(
+    universes: &mut [(UniverseId, DmxUniverse)],
+    candidates: &mut OutputCandidates<'_>,
+    id: UniverseId,
+    output: &OutputConfig,
+    system_out: &Sender<SystemMessage>,
+) {
+    let Some((_, universe)) = universes.iter_mut().find(|(u, _)| *u == id) else {
+        warn!("[DMX] Ignoring output change of universe {id}: not configured");
+        return;
+    };
+
+    // Dropping the old output closes its port or terminates its sACN stream, this has to happen
+    // before the same port can be opened again.
+    *universe = DmxUniverse::new_dummy();
+    let previous = candidates.in_use.remove(&id);
+
+    let selected = match candidates.open(id, output, system_out) {
+        Ok((opened, selected)) => {
+            *universe = opened;
+            info!("[DMX] Universe {id}: switched to {selected}");
+
+            // Restored on the next start, `Auto` is resolved again then.
+            let stored = match output {
+                OutputConfig::Auto => output,
+                _ => &selected,
+            };
+            if let Err(err) = candidates.config.store_output(id, stored) {
+                system_out
+                    .send(SystemMessage::Error(WorkerError::new(
+                        Worker::Dmx,
+                        format!("Failed to store the output of universe {id}: {err:#}"),
+                    )))
+                    .ok();
+            }
+            selected
+        }
+        Err(err) => {
+            system_out
+                .send(SystemMessage::Error(WorkerError::new(
+                    Worker::Dmx,
+                    format!("Universe {id}: {err:#}"),
+                )))
+                .ok();
+
+            let reopened = previous.map(|previous| candidates.open(id, &previous, system_out));
+            match reopened {
+                Some(Ok((opened, previous))) => {
+                    *universe = opened;
+                    info!("[DMX] Universe {id}: kept {previous}");
+                    previous
+                }
+                Some(Err(err)) => {
+                    warn!("[DMX] Universe {id}: failed to reopen the previous output: {err:#}");
+                    OutputConfig::Dummy
+                }
+                None => OutputConfig::Dummy,
+            }
+        }
+    };
+
+    system_out
+        .send(SystemMessage::OutputSelected(id, selected))
+        .ok();
+}
+
+fn report_state(system_out: &Sender<SystemMessage>, id: UniverseId, state: OutputState) {
+    system_out.send(SystemMessage::OutputState(id, state)).ok();
+}
+
+/// Reopens the lost serial interface of a universe and resends the current frame.
+/// Returns whether the universe is connected again.
+fn reconnect(
+    universes: &mut [(UniverseId, DmxUniverse)],
+    candidates: &mut OutputCandidates<'_>,
+    id: UniverseId,
+    frame: Option<&DmxFrame>,
+    system_out: &Sender<SystemMessage>,
+) -> bool {
+    let (Some((_, universe)), Some(lost)) = (
+        universes.iter_mut().find(|(u, _)| *u == id),
+        candidates.in_use.get(&id).cloned(),
+    ) else {
+        return true;
+    };
+
+    let (mut reopened, output) = match candidates.reconnect(id, &lost) {
+        Ok(reconnected) => reconnected,
+        Err(err) => {
+            debug!("[DMX] Universe {id}: {lost} is still gone: {err:#}");
+            return false;
+        }
+    };
+
+    if let Some(frame) = frame {
+        if let Err(err) = reopened.write(frame) {
+            debug!("[DMX] Universe {id}: {output} is not ready yet: {err:#}");
+            return false;
+        }
     }
 
-    fn write_to_serial(&mut self) {
-        self.send_break(Duration::from_micros(100));
-        spin_sleep::sleep(Duration::from_micros(100));
-        self.serial.write_all(&self.channels).unwrap();
-        self.serial.flush().unwrap();
+    *universe = reopened;
+    info!("[DMX] Universe {id}: {output} reconnected");
+    report_state(system_out, id, OutputState::Connected);
+
+    if output != lost {
+        candidates.in_use.insert(id, output.clone());
+        system_out
+            .send(SystemMessage::OutputSelected(id, output))
+            .ok();
     }
+
+    true
 }
 
-pub struct UsbDevice {
-    pub vid: u16,
-    pub pid: u16,
+/// Lists the serial ports every `SERIAL_RESCAN` on its own thread, an enumeration can take longer
+/// than a frame. It stops once the receiver is dropped.
+fn scan_serial_ports() -> anyhow::Result<Receiver<Vec<SerialPortInfo>>> {
+    let (ports_out, ports_in) = crossbeam_channel::bounded(1);
+    thread::Builder::new()
+        .name("serial scan".into())
+        .spawn(move || loop {
+            thread::sleep(SERIAL_RESCAN);
+            if ports_out.send(serial_ports()).is_err() {
+                break;
+            }
+        })
+        .context("Failed to start the serial port scan")?;
+
+    Ok(ports_in)
 }
 
-pub const EUROLITE_USB_DMX512_PRO_CABLE_INTERFACE: UsbDevice = UsbDevice {
-    vid: 1027,
-    pid: 24577,
-};
+/// DMX output thread: transmits all universes at a fixed rate, independent of the audio analysis.
+pub fn dmx_thread(
+    control_receiver: Receiver<DMXControl>,
+    frames: DmxFrames,
+    system_out: Sender<SystemMessage>,
+    config: config::Config,
+) -> anyhow::Result<()> {
+    let mut candidates = OutputCandidates::discover(&config, &system_out);
+    let mut universes = open_universes(&mut candidates, &system_out);
+    let scanned_ports = scan_serial_ports()?;
+
+    let period = Duration::from_secs_f32(1.0 / config.dmx_refresh_rate);
+    info!(
+        "[DMX] Output thread started, refreshing {} universe(s) at {} Hz",
+        universes.len(),
+        config.dmx_refresh_rate
+    );
+
+    let mut next_frame = Instant::now();
+    // Universes whose output fails, a subset of them have lost their serial interface.
+    let mut failing: BTreeSet<UniverseId> = BTreeSet::new();
+    let mut lost: BTreeSet<UniverseId> = BTreeSet::new();
+    let mut next_rescan = Instant::now();
 
-pub const USB_DEVICES: [UsbDevice; 1] = [EUROLITE_USB_DMX512_PRO_CABLE_INTERFACE];
-// const SERIAL_ERROR_RETRY: Duration = Duration::from_secs(5);
+    loop {
+        match control_receiver.try_recv() {
+            Ok(DMXControl::ChangePort(id, output)) => {
+                change_output(&mut universes, &mut candidates, id, &output, &system_out);
+                failing.remove(&id);
+                lost.remove(&id);
+            }
+            Ok(DMXControl::Shutdown) | Err(TryRecvError::Disconnected) => {
+                info!("[DMX] Output thread shutting down...");
+                return Ok(());
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+        if let Ok(ports) = scanned_ports.try_recv() {
+            candidates.update_ports(ports, &system_out);
+        }
 
-pub enum DMXControl {
-    ChangePort(Option<SerialPortInfo>),
+        let snapshot = frames.snapshot();
+
+        for (id, universe) in universes.iter_mut() {
+            let Some(channels) = snapshot.get(id) else {
+                continue;
+            };
+            if lost.contains(id) {
+                continue;
+            }
+
+            match universe.write(channels) {
+                Ok(()) => {
+                    if failing.remove(id) {
+                        info!("[DMX] Universe {id}: output recovered");
+                        report_state(&system_out, *id, OutputState::Connected);
+                    }
+                }
+                Err(err) => {
+                    if failing.insert(*id) {
+                        warn!("[DMX] Universe {id}: output failed: {err:#}");
+                        report_state(&system_out, *id, OutputState::Disconnected);
+                    }
+                    if universe.is_serial() {
+                        // Closes the port, the interface is looked for until it is back.
+                        *universe = DmxUniverse::new_dummy();
+                        lost.insert(*id);
+                    }
+                }
+            }
+        }
+
+        if !lost.is_empty() && Instant::now() >= next_rescan {
+            next_rescan = Instant::now() + SERIAL_RESCAN;
+            lost.retain(|id| {
+                let frame = snapshot.get(id);
+                let back = reconnect(&mut universes, &mut candidates, *id, frame, &system_out);
+                if back {
+                    failing.remove(id);
+                }
+                !back
+            });
+        }
+
+        next_frame += period;
+        let now = Instant::now();
+        if next_frame > now {
+            spin_sleep::sleep(next_frame - now);
+        } else {
+            // Running behind, do not try to catch up by sending bursts.
+            next_frame = now;
+        }
+    }
 }
 
-// pub fn _dmx_thread(
-//     control_receiver: Receiver<DMXControl>,
-//     signal_receiver: Receiver<Signal>,
-//     system_out: Sender<SystemMessage>,
-// ) {
-//     let ports = serialport::available_ports().unwrap();
-//
-//     // Update available ports to frontend.
-//     system_out
-//         .send(SystemMessage::SerialDevicesView(ports.clone()))
-//         .unwrap();
-//
-//     println!("{ports:?}");
-//
-//     let mut port = ports.into_iter().find(|p| {
-//         let SerialPortType::UsbPort(usb) = p.port_type.clone() else {
-//             return false;
-//         };
-//
-//         USB_DEVICES
-//             .iter()
-//             .any(|d| d.pid == usb.pid && d.vid == usb.vid)
-//     });
-//
-//     if port.is_none() {
-//         warn!("No default DMX serial output available");
-//     }
-//
-//     // let Some(port) = port.cloned() else {
-//     //     warn!("[DMX] No default serial device available...");
-//     //     system_out
-//     //         .send(SystemMessage::SerialSelected(None))
-//     //         .unwrap();
-//     // };
-//
-//     // let mut port = Some(port);
-//
-//     loop {
-//         let mut universe = None;
-//
-//         if let Some(port) = port {
-//             let name = port.port_name.clone();
-//             info!("[DMX] Using serial device: {name}");
-//             system_out
-//                 .send(SystemMessage::SerialSelected(Some(port.clone())))
-//                 .unwrap();
-//             universe = Some(DmxUniverse::new(name));
-//         }
-//
-//         'inner: loop {
-//             // Dispatch signals to frontend and to DMX engine.
-//             match signal_receiver.try_recv() {
-//                 Ok(signal) => {
-//                     if let Some(ref mut universe) = universe {
-//                         universe.signal(signal);
-//                     }
-//                 }
-//                 Err(TryRecvError::Empty) => {}
-//                 Err(err) => panic!("{err:?}"),
-//             }
-//
-//             match control_receiver.try_recv() {
-//                 Ok(DMXControl::ChangePort(new_port)) => {
-//                     println!("select port: {new_port:?}");
-//                     port = new_port;
-//                     break 'inner;
-//                 }
-//                 Err(TryRecvError::Empty) => {}
-//                 Err(err) => panic!("{err:?}"),
-//             }
-//
-//             // match system_receiver.try_recv() {
-//             //     Ok(SystemMessage::LoopSpeed(speed)) => todo!(""),
-//             //     // .emit("msg", ToFrontend::Speed(speed.as_micros() as usize)),
-//             //     // .unwrap(),
-//             //     Err(TryRecvError::Empty) => {}
-//             //     Err(err) => panic!("{err:?}"),
-//             // }
-//         }
-//     }
-// }
-//
+/// Asks the analysis thread to stop and waits until it is gone.
+fn stop_analysis(audio_thread_control_signal: &AtomicU8) {
+    // An analysis which stopped on its own is already `DEAD`, that must not be overwritten.
+    audio_thread_control_signal
+        .compare_exchange(
+            AudioThreadControlSignal::CONTINUE,
+            AudioThreadControlSignal::ABORT,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        )
+        .ok();
+
+    while audio_thread_control_signal.load(Ordering::Relaxed) != AudioThreadControlSignal::DEAD {
+        debug!("[audio] Waiting for the analysis to stop...");
+        thread::sleep(Duration::from_millis(100));
+    }
 
-pub fn audio_thread(
-    from_frontend: Receiver<FromFrontend>,
-    audio_thread_control_signal: Arc<AtomicU8>,
-    signal_out_0: Sender<Signal>,
-    system_out: Sender<SystemMessage>,
-) {
-    // let begin_msg = from_frontend.recv().unwrap();
-    println!("[audio] Thread started!");
+    audio_thread_control_signal.store(AudioThreadControlSignal::CONTINUE, Ordering::Relaxed);
+    debug!("[audio] Analysis stopped");
+}
 
-    // let FromFrontend::NewWindow(window) = begin_msg else {
-    //     panic!("Illegal behaviour");
-    // };
+/// The device which was selected last time, if it is still there.
+fn restore_audio_device(
+    config: &config::Config,
+    system_out: &Sender<SystemMessage>,
+) -> Option<Device> {
+    let stored = config.audio_device.as_ref()?;
+    let device = utils::device_from_names(stored.host.clone(), stored.name.clone());
+    if device.is_none() {
+        system_out
+            .send(SystemMessage::Error(WorkerError::new(
+                Worker::Audio,
+                format!("`{}` is not available, select an input", stored.name),
+            )))
+            .ok();
+    }
 
-    // let mut count = 0;
-    // let mut increment = true;
-    // let step = 25;
-    let heartbeat_delay = Duration::from_millis(1000);
+    device
+}
 
-    let mut device: Option<Device> = None;
-    let mut device_changed = false;
+/// Picks the audio input and runs the analysis of it, the analysis is restarted for every
+/// input which is selected.
+pub fn audio_thread(
+    from_frontend: Receiver<FromFrontend>,
+    context: AnalysisContext,
+) -> anyhow::Result<()> {
+    let AnalysisContext {
+        system_out,
+        thread_control_signal: audio_thread_control_signal,
+        app_config: config,
+        ..
+    } = &context;
+
+    info!("[audio] Thread started");
 
-    // From audio to frontend.
-    // let (signal_out, signal_receiver) = mpsc::channel();
-    // let (system_out, system_receiver) = mpsc::channel();
+    // An analysis which is left over from before a restart would run twice.
+    stop_analysis(audio_thread_control_signal);
 
-    // let w = window.clone();
+    let heartbeat_delay = Duration::from_millis(1000);
 
-    // TODO: put the DMX thread under main!
+    // A configured file is analyzed right away, otherwise the last selected device is restored.
+    let mut source: Option<AudioSource> = config
+        .audio_file
+        .clone()
+        .map(AudioSource::File)
+        .or_else(|| restore_audio_device(config, system_out).map(AudioSource::Device));
+    let mut source_changed = source.is_some();
+    let mut analysis_started = false;
 
     loop {
         thread::sleep(heartbeat_delay);
-        // TODO
-        // window.emit("msg", ToFrontend::Heartbeat).unwrap();
 
         match from_frontend.try_recv() {
-            // Ok(FromFrontend::NewWindow(_)) => unreachable!(),
             Ok(FromFrontend::SelectInputDevice(dev)) => {
-                device = dev.clone();
-                device_changed = true;
+                if analysis_started {
+                    stop_analysis(audio_thread_control_signal);
+                    analysis_started = false;
+                }
+
+                // Restored on the next start.
+                let selected = dev
+                    .as_ref()
+                    .and_then(|dev| Some(AudioDevice::new(utils::host_of(dev)?, dev)));
+                if let Err(err) = config.store_audio_device(selected.as_ref()) {
+                    system_out
+                        .send(SystemMessage::Error(WorkerError::new(
+                            Worker::Audio,
+                            format!("Failed to store the selection: {err:#}"),
+                        )))
+                        .ok();
+                }
+
+                source = dev.map(AudioSource::Device);
+                source_changed = true;
             }
             Err(TryRecvError::Empty) => {}
+            // The frontends are gone, the instance is shutting down.
             Err(TryRecvError::Disconnected) => {
-                unreachable!("broken")
+                if analysis_started {
+                    stop_analysis(audio_thread_control_signal);
+                }
+                return Ok(());
             }
         };
 
-        if device.is_none() {
+        if source.is_none() {
             let devices = utils::get_input_devices_flat();
             system_out
                 .send(SystemMessage::AudioDevicesView(devices))
-                .unwrap();
-
-            device_changed = false;
-
-            // let selected_device = devices
-            //     .iter()
-            //     .find(|dev| dev.1.name().unwrap().contains("CABLE Output"))
-            //     .unwrap_or_else(|| &devices[0]);
-            //
-            // let host = selected_device.0.name().to_string();
-            // let device_name = selected_device.1.name().unwrap();
-
-            // println!(
-            //     "{}",
-            //     devices
-            //         .iter()
-            //         .map(|d| d.1.name().unwrap())
-            //         .collect::<Vec<String>>()
-            //         .join("|")
-            // );
-            // println!("Selected default audio device: {host} | {device_name}");
-
-            // device = Some(utils::device_from_names(host, device_name).unwrap());
-            // system_out
-            //     .send(SystemMessage::AudioSelected(device.clone()))
-            //     .unwrap();
-        } else if device_changed {
-            system_out
-                .send(SystemMessage::AudioSelected(device.clone()))
-                .unwrap();
+                .ok();
+
+            source_changed = false;
+        } else if let Some(source) = source.as_ref().filter(|_| source_changed) {
+            if let AudioSource::Device(device) = source {
+                system_out
+                    .send(SystemMessage::AudioSelected(Some(device.clone())))
+                    .ok();
+            }
 
-            let (sig_0, sys) = (signal_out_0.clone(), system_out.clone());
             {
-                let device = device.clone().unwrap();
-                let audio_thread_control_signal = audio_thread_control_signal.clone();
+                let source = source.clone();
+                let context = context.clone();
 
                 thread::spawn(move || {
-                    if let Err(err) = audio::run(
-                        device,
-                        sig_0,
-                        sys.clone(),
-                        audio_thread_control_signal.clone(),
-                    ) {
-                        // TODO: handle the audio backend error.
-                        sys.send(SystemMessage::Log(format!("[audio] {err}")))
-                            .unwrap();
-                    }
-                    audio_thread_control_signal
-                        .store(AudioThreadControlSignal::DEAD, Ordering::Relaxed);
+                    let control_signal = &context.thread_control_signal;
+                    // A failing input, e.g. an unplugged interface, is opened again until
+                    // another one is selected.
+                    supervisor::supervise(
+                        Worker::Analysis,
+                        &context.system_out,
+                        || {
+                            control_signal.load(Ordering::Relaxed)
+                                == AudioThreadControlSignal::ABORT
+                        },
+                        || audio::run(source.clone(), context.clone()),
+                    );
+                    control_signal.store(AudioThreadControlSignal::DEAD, Ordering::Relaxed);
                 });
             }
 
-            device_changed = false;
-            println!(
-                "Started audio detector thread: {}...",
-                device.clone().unwrap().name().unwrap()
-            );
+            source_changed = false;
+            analysis_started = true;
+            info!("[audio] Started the analysis of {source}");
         }
     }
 }
@@ -0,0 +1,285 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+pub const ARTNET_PORT: u16 = 6454;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const PROTOCOL_VERSION: u16 = 14;
+
+const OP_POLL: u16 = 0x2000;
+const OP_POLL_REPLY: u16 = 0x2100;
+const OP_DMX: u16 = 0x5000;
+
+/// Minimum length of an ArtPollReply which still contains the node names.
+const POLL_REPLY_MIN_LEN: usize = 108;
+/// Length of an ArtPollReply up to and including the `SwOut` field.
+const POLL_REPLY_PORTS_LEN: usize = 194;
+/// `PortTypes` flag of a port which can output DMX from the network.
+const PORT_TYPE_OUTPUT: u8 = 0x80;
+
+/// 15-bit Art-Net port address: `net` (7 bit), `subnet` (4 bit) and `universe` (4 bit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PortAddress {
+    pub net: u8,
+    pub subnet: u8,
+    pub universe: u8,
+}
+
+impl PortAddress {
+    pub fn new(net: u8, subnet: u8, universe: u8) -> Result<Self> {
+        if net > 0x7F {
+            bail!("Art-Net net {net} is out of range (0..=127)");
+        }
+        if subnet > 0x0F {
+            bail!("Art-Net subnet {subnet} is out of range (0..=15)");
+        }
+        if universe > 0x0F {
+            bail!("Art-Net universe {universe} is out of range (0..=15)");
+        }
+
+        Ok(Self {
+            net,
+            subnet,
+            universe,
+        })
+    }
+
+    /// Low byte of the port address as used by the `SubUni` field.
+    fn sub_uni(&self) -> u8 {
+        (self.subnet & 0x0F) << 4 | (self.universe & 0x0F)
+    }
+}
+
+impl std::fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.net, self.subnet, self.universe)
+    }
+}
+
+/// An Art-Net node which can be selected as a DMX output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtNetNode {
+    pub name: String,
+    pub address: SocketAddr,
+    #[serde(default)]
+    pub port_address: PortAddress,
+}
+
+fn header(opcode: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(18 + 512);
+    packet.extend_from_slice(ARTNET_ID);
+    packet.extend_from_slice(&opcode.to_le_bytes());
+    packet.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    packet
+}
+
+/// Builds an ArtDmx packet.
+/// `data` are the DMX slots without the start code, the length is padded to an even number.
+pub fn art_dmx_packet(sequence: u8, physical: u8, address: PortAddress, data: &[u8]) -> Vec<u8> {
+    let data = &data[..data.len().min(512)];
+    let length = (data.len().max(2) + 1) & !1;
+
+    let mut packet = header(OP_DMX);
+    packet.push(sequence);
+    packet.push(physical);
+    packet.push(address.sub_uni());
+    packet.push(address.net & 0x7F);
+    packet.extend_from_slice(&(length as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+    packet.resize(18 + length, 0);
+    packet
+}
+
+/// Builds an ArtPoll packet which asks every node to send an ArtPollReply.
+pub fn art_poll_packet() -> Vec<u8> {
+    let mut packet = header(OP_POLL);
+    // Flags: no change notifications, no diagnostics.
+    packet.push(0);
+    // DiagPriority.
+    packet.push(0);
+    packet
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtPollReply {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub firmware_version: u16,
+    pub net_switch: u8,
+    pub sub_switch: u8,
+    pub oem: u16,
+    pub short_name: String,
+    pub long_name: String,
+    /// `SwOut` of the node's first output port, its universe is in the low nibble.
+    pub sw_out: u8,
+}
+
+impl ArtPollReply {
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < POLL_REPLY_MIN_LEN || &packet[0..8] != ARTNET_ID {
+            return None;
+        }
+
+        if u16::from_le_bytes([packet[8], packet[9]]) != OP_POLL_REPLY {
+            return None;
+        }
+
+        let name = |bytes: &[u8]| {
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+        };
+
+        // Replies of old nodes may end before the port fields.
+        let sw_out = if packet.len() >= POLL_REPLY_PORTS_LEN {
+            let port_types = &packet[174..178];
+            let output = port_types
+                .iter()
+                .position(|port_type| port_type & PORT_TYPE_OUTPUT != 0)
+                .unwrap_or(0);
+            packet[190 + output]
+        } else {
+            0
+        };
+
+        Some(Self {
+            ip: Ipv4Addr::new(packet[10], packet[11], packet[12], packet[13]),
+            port: u16::from_le_bytes([packet[14], packet[15]]),
+            firmware_version: u16::from_be_bytes([packet[16], packet[17]]),
+            net_switch: packet[18],
+            sub_switch: packet[19],
+            oem: u16::from_be_bytes([packet[20], packet[21]]),
+            short_name: name(&packet[26..44]),
+            long_name: name(&packet[44..108]),
+            sw_out,
+        })
+    }
+
+    pub fn node(&self) -> ArtNetNode {
+        let name = if self.short_name.is_empty() {
+            self.ip.to_string()
+        } else {
+            self.short_name.clone()
+        };
+
+        ArtNetNode {
+            name,
+            address: SocketAddr::V4(SocketAddrV4::new(self.ip, ARTNET_PORT)),
+            port_address: PortAddress {
+                net: self.net_switch & 0x7F,
+                subnet: self.sub_switch & 0x0F,
+                universe: self.sw_out & 0x0F,
+            },
+        }
+    }
+}
+
+/// Binds the Art-Net port, as nodes send their ArtPollReply to it.
+/// The port is shared with other Art-Net software on this host, if that fails a random port is
+/// used which only receives replies from nodes that answer to the sender's port.
+fn discovery_socket() -> Result<UdpSocket> {
+    match bind_shared(ARTNET_PORT) {
+        Ok(socket) => Ok(socket),
+        Err(err) => {
+            warn!("Could not bind port {ARTNET_PORT}, some Art-Net nodes may not be found: {err}");
+            Ok(UdpSocket::bind(("0.0.0.0", 0))?)
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    Ok(socket.into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+}
+
+/// Broadcasts an ArtPoll and collects all replies which arrive within `timeout`.
+pub fn discover(timeout: Duration) -> Result<Vec<ArtPollReply>> {
+    let socket = discovery_socket()?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&art_poll_packet(), (Ipv4Addr::BROADCAST, ARTNET_PORT))?;
+
+    let mut replies: Vec<ArtPollReply> = vec![];
+    let mut buf = [0; 1024];
+    let start = Instant::now();
+
+    while let Some(remaining) = timeout.checked_sub(start.elapsed()) {
+        if remaining.is_zero() {
+            break;
+        }
+
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, _)) => {
+                let Some(reply) = ArtPollReply::parse(&buf[..len]) else {
+                    continue;
+                };
+
+                if !replies.contains(&reply) {
+                    replies.push(reply);
+                }
+            }
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(replies)
+}
+
+pub struct ArtNetOutput {
+    socket: UdpSocket,
+    node: ArtNetNode,
+    sequence: u8,
+}
+
+impl ArtNetOutput {
+    pub fn new(node: ArtNetNode) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        // Required if the target is a (directed) broadcast address.
+        socket.set_broadcast(true)?;
+
+        Ok(Self {
+            socket,
+            node,
+            sequence: 0,
+        })
+    }
+
+    pub fn node(&self) -> &ArtNetNode {
+        &self.node
+    }
+
+    /// Sends the 513-byte channel buffer, the start code in slot 0 is not transmitted.
+    pub fn send(&mut self, channels: &[u8; 513]) -> io::Result<()> {
+        // Sequence 0 disables reordering on the receiver, therefore it is skipped.
+        self.sequence = self.sequence.checked_add(1).unwrap_or(1);
+
+        let packet = art_dmx_packet(self.sequence, 0, self.node.port_address, &channels[1..]);
+        self.socket.send_to(&packet, self.node.address)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
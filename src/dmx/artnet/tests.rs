@@ -0,0 +1,163 @@
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    time::Duration,
+};
+
+use super::{
+    art_dmx_packet, art_poll_packet, ArtNetNode, ArtNetOutput, ArtPollReply, PortAddress,
+    ARTNET_PORT,
+};
+
+/// A node on the loopback interface and the socket which receives its packets.
+fn loopback_node(port_address: PortAddress) -> (ArtNetNode, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let node = ArtNetNode {
+        name: "test".to_string(),
+        address: receiver.local_addr().unwrap(),
+        port_address,
+    };
+    (node, receiver)
+}
+
+fn receive(socket: &UdpSocket) -> Vec<u8> {
+    let mut buffer = [0; 1024];
+    let len = socket.recv(&mut buffer).unwrap();
+    buffer[..len].to_vec()
+}
+
+#[test]
+fn art_dmx_is_sent_to_the_node() {
+    let (node, receiver) = loopback_node(PortAddress::new(1, 2, 3).unwrap());
+    let mut output = ArtNetOutput::new(node).unwrap();
+
+    let mut channels = [0; 513];
+    channels[0] = 0xFF;
+    for (slot, value) in channels[1..].iter_mut().enumerate() {
+        *value = slot as u8;
+    }
+    output.send(&channels).unwrap();
+
+    let packet = receive(&receiver);
+    assert_eq!(packet.len(), 18 + 512);
+    assert_eq!(packet[..8], *b"Art-Net\0");
+    // OpDmx (little endian), protocol version 14 (big endian).
+    assert_eq!(packet[8..12], [0x00, 0x50, 0x00, 14]);
+    // Sequence, physical, SubUni, Net, length.
+    assert_eq!(packet[12..18], [1, 0, 0x23, 1, 0x02, 0x00]);
+    // The start code is not transmitted.
+    assert_eq!(packet[18..], channels[1..]);
+}
+
+#[test]
+fn sequence_skips_zero_when_it_wraps() {
+    let (node, receiver) = loopback_node(PortAddress::default());
+    let mut output = ArtNetOutput::new(node).unwrap();
+
+    let sequences: Vec<u8> = (0..257)
+        .map(|_| {
+            output.send(&[0; 513]).unwrap();
+            receive(&receiver)[12]
+        })
+        .collect();
+
+    assert_eq!(sequences[..3], [1, 2, 3]);
+    assert_eq!(sequences[254..], [255, 1, 2]);
+}
+
+#[test]
+fn short_frames_are_padded_to_an_even_length() {
+    let packet = art_dmx_packet(7, 0, PortAddress::default(), &[10, 20, 30]);
+    assert_eq!(packet[16..], [0x00, 0x04, 10, 20, 30, 0]);
+
+    let packet = art_dmx_packet(7, 0, PortAddress::default(), &[]);
+    assert_eq!(packet[16..], [0x00, 0x02, 0, 0]);
+}
+
+#[test]
+fn art_poll_asks_for_replies() {
+    assert_eq!(
+        art_poll_packet(),
+        b"Art-Net\0\x00\x20\x00\x0e\x00\x00".to_vec()
+    );
+}
+
+/// An ArtPollReply as a node sends it, only the fields which are parsed are set.
+fn poll_reply() -> Vec<u8> {
+    let mut packet = vec![0; 239];
+    packet[..8].copy_from_slice(b"Art-Net\0");
+    packet[8..10].copy_from_slice(&[0x00, 0x21]);
+    packet[10..14].copy_from_slice(&[192, 168, 1, 50]);
+    packet[14..16].copy_from_slice(&ARTNET_PORT.to_le_bytes());
+    packet[16..18].copy_from_slice(&[0x01, 0x02]);
+    packet[18] = 0x81;
+    packet[19] = 0x15;
+    packet[20..22].copy_from_slice(&[0x04, 0x30]);
+    packet[26..35].copy_from_slice(b"Stage DMX");
+    packet[44..60].copy_from_slice(b"Stage DMX node 2");
+    // Port 1 is an input, port 2 outputs universe 7.
+    packet[172..174].copy_from_slice(&[0, 2]);
+    packet[174..176].copy_from_slice(&[0x40, 0x80]);
+    packet[190..192].copy_from_slice(&[0x13, 0x17]);
+    packet
+}
+
+#[test]
+fn poll_replies_describe_the_node() {
+    // Received like `discover` does.
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .send_to(&poll_reply(), receiver.local_addr().unwrap())
+        .unwrap();
+    let reply = ArtPollReply::parse(&receive(&receiver)).unwrap();
+
+    assert_eq!(
+        reply,
+        ArtPollReply {
+            ip: Ipv4Addr::new(192, 168, 1, 50),
+            port: ARTNET_PORT,
+            firmware_version: 0x0102,
+            net_switch: 0x81,
+            sub_switch: 0x15,
+            oem: 0x0430,
+            short_name: "Stage DMX".to_string(),
+            long_name: "Stage DMX node 2".to_string(),
+            sw_out: 0x17,
+        }
+    );
+
+    let node = reply.node();
+    assert_eq!(node.name, "Stage DMX");
+    assert_eq!(node.address, "192.168.1.50:6454".parse().unwrap());
+    assert_eq!(node.port_address, PortAddress::new(1, 5, 7).unwrap());
+
+    // Without the port fields.
+    let reply = ArtPollReply::parse(&poll_reply()[..108]).unwrap();
+    assert_eq!(reply.sw_out, 0);
+    assert_eq!(
+        reply.node().port_address,
+        PortAddress::new(1, 5, 0).unwrap()
+    );
+}
+
+#[test]
+fn other_packets_are_not_poll_replies() {
+    let reply = poll_reply();
+    assert!(ArtPollReply::parse(&reply[..107]).is_none());
+    assert!(ArtPollReply::parse(&art_poll_packet()).is_none());
+
+    let mut other = reply.clone();
+    other[..8].copy_from_slice(b"Art-Nex\0");
+    assert!(ArtPollReply::parse(&other).is_none());
+}
+
+#[test]
+fn port_addresses_are_range_checked() {
+    assert!(PortAddress::new(127, 15, 15).is_ok());
+    assert!(PortAddress::new(128, 0, 0).is_err());
+    assert!(PortAddress::new(0, 16, 0).is_err());
+    assert!(PortAddress::new(0, 0, 16).is_err());
+}
@@ -0,0 +1,190 @@
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serialport::SerialPort;
+
+const START_OF_MESSAGE: u8 = 0x7E;
+const END_OF_MESSAGE: u8 = 0xE7;
+
+const LABEL_GET_WIDGET_PARAMETERS: u8 = 3;
+const LABEL_OUTPUT_ONLY_SEND_DMX: u8 = 6;
+const LABEL_GET_WIDGET_SERIAL_NUMBER: u8 = 10;
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Parameters as reported by the widget, times are converted to microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WidgetParameters {
+    pub firmware_version: u16,
+    pub break_time_us: u32,
+    pub mark_after_break_time_us: u32,
+    /// DMX output rate in packets per second, 0 means as fast as possible.
+    pub output_rate: u8,
+}
+
+impl WidgetParameters {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 5 {
+            bail!(
+                "widget parameters reply is too short ({} bytes)",
+                data.len()
+            );
+        }
+
+        // Break and MAB times are reported in units of 10.67us.
+        let to_us = |units: u8| (units as u32 * 1067) / 100;
+
+        Ok(Self {
+            firmware_version: u16::from_le_bytes([data[0], data[1]]),
+            break_time_us: to_us(data[2]),
+            mark_after_break_time_us: to_us(data[3]),
+            output_rate: data[4],
+        })
+    }
+}
+
+/// Frames a message for the widget: start, label, length (LSB first), data, end.
+pub fn message(label: u8, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(data.len() + 5);
+    message.push(START_OF_MESSAGE);
+    message.push(label);
+    message.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    message.extend_from_slice(data);
+    message.push(END_OF_MESSAGE);
+    message
+}
+
+/// Driver for the Enttec DMX USB Pro widget protocol and compatible interfaces.
+pub struct EnttecProOutput {
+    serial: Box<dyn SerialPort>,
+    parameters: Option<WidgetParameters>,
+    serial_number: Option<u32>,
+}
+
+impl EnttecProOutput {
+    pub fn new(port_path: &str) -> Result<Self> {
+        // The widget is a virtual COM port, the baud rate does not matter.
+        let serial = serialport::new(port_path, 57600)
+            .timeout(Duration::from_millis(10))
+            .open()
+            .with_context(|| format!("Failed to open port `{port_path}`"))?;
+
+        let mut output = Self {
+            serial,
+            parameters: None,
+            serial_number: None,
+        };
+
+        output.serial.clear(serialport::ClearBuffer::All)?;
+
+        // Many compatible interfaces only implement sending DMX, therefore the widget is used
+        // even if it does not answer these queries.
+        output.parameters = output
+            .query(LABEL_GET_WIDGET_PARAMETERS, &[0, 0])
+            .and_then(|data| WidgetParameters::parse(&data))
+            .map_err(|err| warn!("[DMX] `{port_path}` did not report its parameters: {err:#}"))
+            .ok();
+
+        output.serial_number = output
+            .query(LABEL_GET_WIDGET_SERIAL_NUMBER, &[])
+            .and_then(|data| serial_number(&data))
+            .map_err(|err| warn!("[DMX] `{port_path}` did not report its serial number: {err:#}"))
+            .ok();
+
+        Ok(output)
+    }
+
+    /// The widget's parameters, `None` if it did not report them.
+    pub fn parameters(&self) -> Option<WidgetParameters> {
+        self.parameters
+    }
+
+    /// The widget's serial number, `None` if it did not report it.
+    pub fn serial_number(&self) -> Option<u32> {
+        self.serial_number
+    }
+
+    fn send(&mut self, label: u8, data: &[u8]) -> io::Result<()> {
+        self.serial.write_all(&message(label, data))?;
+        self.serial.flush()
+    }
+
+    /// Sends a request and waits for the reply with the same label.
+    fn query(&mut self, label: u8, data: &[u8]) -> Result<Vec<u8>> {
+        self.send(label, data)?;
+        self.receive(label)
+    }
+
+    fn read_byte(&mut self, deadline: Instant) -> Result<u8> {
+        let mut byte = [0];
+        loop {
+            match self.serial.read(&mut byte) {
+                Ok(1) => return Ok(byte[0]),
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            if Instant::now() >= deadline {
+                bail!("timed out waiting for a reply from the widget");
+            }
+        }
+    }
+
+    /// Reads messages until one with the given label arrives, others are skipped.
+    fn receive(&mut self, label: u8) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+
+        loop {
+            if self.read_byte(deadline)? != START_OF_MESSAGE {
+                continue;
+            }
+
+            let this_label = self.read_byte(deadline)?;
+            let len = u16::from_le_bytes([self.read_byte(deadline)?, self.read_byte(deadline)?]);
+
+            let mut data = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                data.push(self.read_byte(deadline)?);
+            }
+
+            if self.read_byte(deadline)? != END_OF_MESSAGE {
+                continue;
+            }
+
+            if this_label == label {
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Sends the 513-byte channel buffer using "Output Only Send DMX".
+    pub fn write(&mut self, channels: &[u8; 513]) -> io::Result<()> {
+        let mut data = *channels;
+        // Null start code.
+        data[0] = 0;
+
+        self.send(LABEL_OUTPUT_ONLY_SEND_DMX, &data)
+    }
+}
+
+/// The serial number is reported as 4 BCD-encoded bytes, LSB first.
+fn serial_number(data: &[u8]) -> Result<u32> {
+    if data.len() < 4 {
+        bail!("serial number reply is too short ({} bytes)", data.len());
+    }
+
+    let mut serial_number = 0;
+    for byte in data[..4].iter().rev() {
+        serial_number = serial_number * 100 + (byte >> 4) as u32 * 10 + (byte & 0x0F) as u32;
+    }
+
+    Ok(serial_number)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,41 @@
+use super::{message, serial_number, WidgetParameters, LABEL_OUTPUT_ONLY_SEND_DMX};
+
+#[test]
+fn messages_are_framed() {
+    assert_eq!(
+        message(LABEL_OUTPUT_ONLY_SEND_DMX, &[0, 255, 128]),
+        [0x7E, 6, 3, 0, 0, 255, 128, 0xE7]
+    );
+    assert_eq!(message(10, &[]), [0x7E, 10, 0, 0, 0xE7]);
+
+    // The length is sent LSB first.
+    let message = message(LABEL_OUTPUT_ONLY_SEND_DMX, &[0; 513]);
+    assert_eq!(message.len(), 513 + 5);
+    assert_eq!(message[2..4], [0x01, 0x02]);
+    assert_eq!(message.last(), Some(&0xE7));
+}
+
+#[test]
+fn widget_parameters_are_converted_to_microseconds() {
+    assert_eq!(
+        WidgetParameters::parse(&[0x44, 0x01, 9, 1, 40]).unwrap(),
+        WidgetParameters {
+            firmware_version: 0x0144,
+            break_time_us: 96,
+            mark_after_break_time_us: 10,
+            output_rate: 40,
+        }
+    );
+
+    // User configuration data may follow.
+    assert!(WidgetParameters::parse(&[0, 0, 0, 0, 0, 1, 2]).is_ok());
+    assert!(WidgetParameters::parse(&[0x44, 0x01, 9, 1]).is_err());
+}
+
+#[test]
+fn serial_numbers_are_bcd_encoded() {
+    assert_eq!(serial_number(&[0x78, 0x56, 0x34, 0x12]).unwrap(), 12345678);
+    assert_eq!(serial_number(&[0x99, 0x99, 0x99, 0x99]).unwrap(), 99999999);
+    assert_eq!(serial_number(&[0x01, 0x00, 0x00, 0x00]).unwrap(), 1);
+    assert!(serial_number(&[0x78, 0x56, 0x34]).is_err());
+}
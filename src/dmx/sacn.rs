@@ -0,0 +1,309 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const SACN_PORT: u16 = 5568;
+
+pub const DEFAULT_PRIORITY: u8 = 100;
+pub const MAX_PRIORITY: u8 = 200;
+
+const ACN_PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+const OPTION_STREAM_TERMINATED: u8 = 0x40;
+
+const SOURCE_NAME_LEN: usize = 64;
+
+/// Offsets of the three PDU layers inside a data packet.
+const ROOT_LAYER: usize = 16;
+const FRAMING_LAYER: usize = 38;
+const DMP_LAYER: usize = 115;
+const HEADER_LEN: usize = 125;
+
+/// E1.31 requires three terminated packets so that receivers notice reliably.
+const TERMINATION_PACKETS: usize = 3;
+
+/// Component identifier of a sACN source, a UUID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cid(pub [u8; 16]);
+
+impl Cid {
+    /// Generates a random (version 4) UUID.
+    pub fn generate() -> Self {
+        let random = || {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos(),
+            );
+            hasher.finish().to_be_bytes()
+        };
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&random());
+        bytes[8..].copy_from_slice(&random());
+
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+
+        Self(bytes)
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let hex: Vec<u8> = s.bytes().filter(|c| *c != b'-').collect();
+        // Also rules out multi-byte characters, which the byte offsets below would split.
+        if hex.len() != 32 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            bail!("CID `{s}` is not a UUID");
+        }
+
+        let digit = |c: u8| (c as char).to_digit(16).unwrap_or_default() as u8;
+        let mut bytes = [0; 16];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+            *byte = digit(pair[0]) << 4 | digit(pair[1]);
+        }
+
+        Ok(Self(bytes))
+    }
+}
+
+impl std::fmt::Display for Cid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Cid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Cid::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Identity of this sACN source on the network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SacnSource {
+    pub name: String,
+    /// Generated and stored in the config file when it is read, the web build has no clock to
+    /// seed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<Cid>,
+    /// Merge priority used by receivers, `0..=200`.
+    pub priority: u8,
+}
+
+impl Default for SacnSource {
+    fn default() -> Self {
+        Self {
+            name: "blaulicht".to_string(),
+            cid: None,
+            priority: DEFAULT_PRIORITY,
+        }
+    }
+}
+
+/// A sACN universe which can be selected as a DMX output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SacnUniverse {
+    pub universe: u16,
+    /// Unicast destination, the universe's multicast group is used if this is not set.
+    #[serde(default)]
+    pub unicast: Option<IpAddr>,
+    /// Overrides the source priority for this universe.
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+impl SacnUniverse {
+    pub fn destination(&self) -> SocketAddr {
+        match self.unicast {
+            Some(ip) => SocketAddr::new(ip, SACN_PORT),
+            None => SocketAddr::new(IpAddr::V4(multicast_address(self.universe)), SACN_PORT),
+        }
+    }
+}
+
+impl std::fmt::Display for SacnUniverse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.unicast {
+            Some(ip) => write!(f, "{} (unicast {ip})", self.universe),
+            None => write!(f, "{} (multicast)", self.universe),
+        }
+    }
+}
+
+/// Multicast group of a universe: `239.255.<universe hi>.<universe lo>`.
+pub fn multicast_address(universe: u16) -> Ipv4Addr {
+    let [hi, lo] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+fn flags_and_length(length: usize) -> [u8; 2] {
+    (0x7000 | (length as u16 & 0x0FFF)).to_be_bytes()
+}
+
+/// Builds an E1.31 data packet.
+/// `data` are the DMX slots without the start code, a source without a CID sends the nil UUID.
+pub fn data_packet(
+    source: &SacnSource,
+    priority: u8,
+    universe: u16,
+    sequence: u8,
+    options: u8,
+    data: &[u8],
+) -> Vec<u8> {
+    let data = &data[..data.len().min(512)];
+    let length = HEADER_LEN + 1 + data.len();
+
+    let mut packet = Vec::with_capacity(length);
+
+    // Root layer.
+    packet.extend_from_slice(&0x0010u16.to_be_bytes());
+    packet.extend_from_slice(&0x0000u16.to_be_bytes());
+    packet.extend_from_slice(ACN_PACKET_IDENTIFIER);
+    packet.extend_from_slice(&flags_and_length(length - ROOT_LAYER));
+    packet.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+    packet.extend_from_slice(&source.cid.unwrap_or_default().0);
+
+    // Framing layer.
+    debug_assert_eq!(packet.len(), FRAMING_LAYER);
+    packet.extend_from_slice(&flags_and_length(length - FRAMING_LAYER));
+    packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+    let mut name = [0; SOURCE_NAME_LEN];
+    let name_len = source.name.len().min(SOURCE_NAME_LEN - 1);
+    name[..name_len].copy_from_slice(&source.name.as_bytes()[..name_len]);
+    packet.extend_from_slice(&name);
+    packet.push(priority.min(MAX_PRIORITY));
+    // Synchronization address: not synchronized.
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.push(sequence);
+    packet.push(options);
+    packet.extend_from_slice(&universe.to_be_bytes());
+
+    // DMP layer.
+    debug_assert_eq!(packet.len(), DMP_LAYER);
+    packet.extend_from_slice(&flags_and_length(length - DMP_LAYER));
+    packet.push(VECTOR_DMP_SET_PROPERTY);
+    // Address type & data type.
+    packet.push(0xA1);
+    // First property address.
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    // Address increment.
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+    // DMX start code.
+    packet.push(0);
+    packet.extend_from_slice(data);
+
+    packet
+}
+
+pub struct SacnOutput {
+    socket: UdpSocket,
+    source: SacnSource,
+    universe: SacnUniverse,
+    sequence: u8,
+    terminated: bool,
+}
+
+impl SacnOutput {
+    pub fn new(mut source: SacnSource, universe: SacnUniverse) -> Result<Self> {
+        if !(1..=63999).contains(&universe.universe) {
+            bail!(
+                "sACN universe {} is out of range (1..=63999)",
+                universe.universe
+            );
+        }
+
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        if universe.unicast.is_none() {
+            socket.set_multicast_ttl_v4(8)?;
+        }
+        source.cid.get_or_insert_with(Cid::generate);
+
+        Ok(Self {
+            socket,
+            source,
+            universe,
+            sequence: 0,
+            terminated: false,
+        })
+    }
+
+    pub fn universe(&self) -> &SacnUniverse {
+        &self.universe
+    }
+
+    fn send_packet(&mut self, options: u8, data: &[u8]) -> io::Result<()> {
+        let priority = self.universe.priority.unwrap_or(self.source.priority);
+        let packet = data_packet(
+            &self.source,
+            priority,
+            self.universe.universe,
+            self.sequence,
+            options,
+            data,
+        );
+        self.sequence = self.sequence.wrapping_add(1);
+
+        self.socket.send_to(&packet, self.universe.destination())?;
+        Ok(())
+    }
+
+    /// Sends the 513-byte channel buffer, slot 0 is replaced by the null start code.
+    pub fn send(&mut self, channels: &[u8; 513]) -> io::Result<()> {
+        self.terminated = false;
+        self.send_packet(0, &channels[1..])
+    }
+
+    /// Tells all receivers that this source stops sending the universe.
+    pub fn terminate(&mut self) -> io::Result<()> {
+        if self.terminated {
+            return Ok(());
+        }
+
+        for _ in 0..TERMINATION_PACKETS {
+            self.send_packet(OPTION_STREAM_TERMINATED, &[])?;
+        }
+
+        self.terminated = true;
+        Ok(())
+    }
+}
+
+impl Drop for SacnOutput {
+    fn drop(&mut self) {
+        if let Err(err) = self.terminate() {
+            log::warn!(
+                "[DMX] Failed to terminate sACN universe {}: {err}",
+                self.universe.universe
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,119 @@
+use std::net::Ipv4Addr;
+
+use super::{data_packet, multicast_address, Cid, SacnSource, OPTION_STREAM_TERMINATED};
+
+const CID: &str = "a1b2c3d4-e5f6-4789-8abc-def012345678";
+
+fn source(name: &str) -> SacnSource {
+    SacnSource {
+        name: name.to_string(),
+        cid: Some(Cid::parse(CID).unwrap()),
+        priority: 100,
+    }
+}
+
+#[test]
+fn full_universe_matches_the_e131_layout() {
+    let data: Vec<u8> = (0..512).map(|slot| slot as u8).collect();
+    let packet = data_packet(&source("blaulicht"), 100, 0x0102, 7, 0, &data);
+    assert_eq!(packet.len(), 638);
+
+    // Root layer: preamble, postamble, ACN identifier, flags & length, vector, CID.
+    let mut root = vec![0x00, 0x10, 0x00, 0x00];
+    root.extend(b"ASC-E1.17\0\0\0");
+    root.extend([0x72, 0x6E, 0x00, 0x00, 0x00, 0x04]);
+    root.extend(Cid::parse(CID).unwrap().0);
+    assert_eq!(packet[..38], root[..]);
+
+    // Framing layer: flags & length, vector, source name, priority, sync address, sequence,
+    // options, universe.
+    let mut framing = vec![0x72, 0x58, 0x00, 0x00, 0x00, 0x02];
+    framing.extend(b"blaulicht");
+    framing.extend([0; 55]);
+    framing.extend([100, 0x00, 0x00, 7, 0, 0x01, 0x02]);
+    assert_eq!(packet[38..115], framing[..]);
+
+    // DMP layer: flags & length, vector, address & data type, first address, increment,
+    // property count, start code.
+    assert_eq!(
+        packet[115..126],
+        [0x72, 0x0B, 0x02, 0xA1, 0x00, 0x00, 0x00, 0x01, 0x02, 0x01, 0x00]
+    );
+    assert_eq!(packet[126..], data[..]);
+}
+
+#[test]
+fn lengths_follow_the_number_of_slots() {
+    let packet = data_packet(&source("blaulicht"), 100, 1, 0, 0, &[255; 3]);
+    assert_eq!(packet.len(), 129);
+
+    assert_eq!(packet[16..18], [0x70, 113]);
+    assert_eq!(packet[38..40], [0x70, 91]);
+    assert_eq!(packet[115..117], [0x70, 14]);
+    assert_eq!(packet[123..125], [0x00, 4]);
+    assert_eq!(packet[125..], [0, 255, 255, 255]);
+}
+
+#[test]
+fn termination_packets_carry_no_slots() {
+    let packet = data_packet(
+        &source("blaulicht"),
+        100,
+        1,
+        0,
+        OPTION_STREAM_TERMINATED,
+        &[],
+    );
+
+    assert_eq!(packet.len(), 126);
+    assert_eq!(packet[112], 0x40);
+    assert_eq!(packet[123..], [0x00, 0x01, 0x00]);
+}
+
+#[test]
+fn source_name_and_priority_are_limited() {
+    let packet = data_packet(&source(&"x".repeat(100)), 255, 1, 0, 0, &[]);
+
+    // The name is NUL-terminated within its 64 bytes.
+    assert!(packet[44..107].iter().all(|&byte| byte == b'x'));
+    assert_eq!(packet[107], 0);
+    assert_eq!(packet[108], 200);
+}
+
+#[test]
+fn data_beyond_512_slots_is_dropped() {
+    let packet = data_packet(&source("blaulicht"), 100, 1, 0, 0, &[1; 600]);
+
+    assert_eq!(packet.len(), 638);
+    assert_eq!(packet[123..125], [0x02, 0x01]);
+}
+
+#[test]
+fn cids_are_formatted_as_uuids() {
+    let cid = Cid::parse(CID).unwrap();
+    assert_eq!(cid.to_string(), CID);
+    assert_eq!(Cid::parse(&CID.replace('-', "")).unwrap(), cid);
+    assert!(Cid::parse("a1b2c3d4").is_err());
+    assert!(Cid::parse(&CID.replace('a', "A")).is_ok());
+
+    // Non-ASCII characters and signs are not hex digits.
+    for invalid in [
+        "a1b2c3d4-e5f6-4789-8abc-def0123456ä",
+        "ä1b2c3d4-e5f6-4789-8abc-def01234567",
+        "+1b2c3d4-e5f6-4789-8abc-def012345678",
+        "a1b2c3d4-e5f6-4789-8abc-def01234567g",
+    ] {
+        assert!(Cid::parse(invalid).is_err(), "{invalid}");
+    }
+
+    // Version 4, RFC 4122 variant.
+    let generated = Cid::generate();
+    assert_eq!(generated.0[6] >> 4, 4);
+    assert_eq!(generated.0[8] >> 6, 0b10);
+}
+
+#[test]
+fn universes_map_to_their_multicast_group() {
+    assert_eq!(multicast_address(1), Ipv4Addr::new(239, 255, 0, 1));
+    assert_eq!(multicast_address(0x0102), Ipv4Addr::new(239, 255, 1, 2));
+}
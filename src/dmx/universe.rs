@@ -0,0 +1,155 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{artnet::ArtNetNode, sacn::SacnUniverse, DmxFrame, SerialDriver, UsbId};
+
+/// Identifies a DMX universe inside blaulicht, independent of its output.
+pub type UniverseId = u16;
+
+/// An absolute DMX slot: universe and address (`1..=512`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct DmxAddress {
+    pub universe: UniverseId,
+    pub address: u16,
+}
+
+impl DmxAddress {
+    pub fn new(universe: UniverseId, address: u16) -> Result<Self> {
+        if !(1..=512).contains(&address) {
+            bail!("DMX address {address} is out of range (1..=512)");
+        }
+
+        Ok(Self { universe, address })
+    }
+}
+
+impl std::fmt::Display for DmxAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:03}", self.universe, self.address)
+    }
+}
+
+/// Where the frame of a universe is sent to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputConfig {
+    /// The frame is computed but not sent anywhere.
+    Dummy,
+    /// The first known USB interface, Art-Net node or sACN universe which is available.
+    Auto,
+    Serial {
+        port: String,
+        /// Detected from the USB VID/PID if not set.
+        #[serde(default)]
+        driver: Option<SerialDriver>,
+        /// Finds the interface again if it is plugged into another port, `port` is ignored then.
+        #[serde(default)]
+        usb: Option<UsbId>,
+    },
+    ArtNet(ArtNetNode),
+    Sacn(SacnUniverse),
+}
+
+impl OutputConfig {
+    /// Whether both send to the same serial port, Art-Net node or sACN universe, regardless of
+    /// the other settings.
+    pub fn same_output(&self, other: &OutputConfig) -> bool {
+        match (self, other) {
+            (OutputConfig::Serial { port: a, .. }, OutputConfig::Serial { port: b, .. }) => a == b,
+            (OutputConfig::ArtNet(a), OutputConfig::ArtNet(b)) => a.address == b.address,
+            (OutputConfig::Sacn(a), OutputConfig::Sacn(b)) => a.universe == b.universe,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl std::fmt::Display for OutputConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputConfig::Dummy => write!(f, "NONE"),
+            OutputConfig::Auto => write!(f, "auto"),
+            OutputConfig::Serial { port, .. } => write!(f, "{port}"),
+            OutputConfig::ArtNet(node) => write!(
+                f,
+                "Art-Net {} ({}, {})",
+                node.name, node.address, node.port_address
+            ),
+            OutputConfig::Sacn(universe) => write!(f, "sACN {universe}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UniverseConfig {
+    pub id: UniverseId,
+    pub output: OutputConfig,
+}
+
+impl UniverseConfig {
+    /// Used if no universes are configured: a single universe on the first available output.
+    pub fn default_universes() -> Vec<Self> {
+        vec![Self {
+            id: 1,
+            output: OutputConfig::Auto,
+        }]
+    }
+}
+
+pub fn validate_universes(universes: &[UniverseConfig]) -> Result<()> {
+    for (i, universe) in universes.iter().enumerate() {
+        if universes[..i].iter().any(|u| u.id == universe.id) {
+            bail!("Universe {} is configured more than once", universe.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Frame buffers of all universes, written by the show and read by the DMX output thread.
+#[derive(Clone)]
+pub struct DmxFrames {
+    frames: Arc<Mutex<BTreeMap<UniverseId, DmxFrame>>>,
+}
+
+impl DmxFrames {
+    pub fn new(universes: impl IntoIterator<Item = UniverseId>) -> Self {
+        Self {
+            frames: Arc::new(Mutex::new(
+                universes.into_iter().map(|id| (id, [0; 513])).collect(),
+            )),
+        }
+    }
+
+    /// A frame is only ever replaced as a whole, so one left behind by a panic is still valid.
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<UniverseId, DmxFrame>> {
+        self.frames.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn universes(&self) -> Vec<UniverseId> {
+        self.lock().keys().copied().collect()
+    }
+
+    pub fn get(&self, universe: UniverseId) -> Option<DmxFrame> {
+        self.lock().get(&universe).copied()
+    }
+
+    /// Copies all frames at once so that universes stay consistent with each other.
+    pub fn snapshot(&self) -> BTreeMap<UniverseId, DmxFrame> {
+        self.lock().clone()
+    }
+
+    /// Replaces the given frames, universes which do not exist are ignored.
+    pub fn update(&self, frames: &BTreeMap<UniverseId, DmxFrame>) {
+        let mut shared = self.lock();
+        for (id, frame) in frames {
+            if let Some(shared_frame) = shared.get_mut(id) {
+                *shared_frame = *frame;
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{sections::Section, Signal},
+    dmx::{
+        universe::{DmxAddress, DmxFrames, UniverseId},
+        DmxFrame,
+    },
+    fixture::{
+        patch::{FixtureId, Patch},
+        ChannelRole,
+    },
+};
+
+pub mod builtin;
+
+use builtin::{BassStrobe, BeatFlash, IdleColor, VolumeColor};
+
+/// What an effect sees when it runs.
+#[derive(Debug, Clone, Copy)]
+pub struct EffectContext {
+    pub now: Instant,
+    /// Time between two DMX frames, changes shorter than this might never be sent.
+    pub frame_duration: Duration,
+}
+
+/// Fixture attributes the effects write into, backed by the frames of all universes.
+pub struct Attributes<'a> {
+    patch: &'a Patch,
+    channels: &'a mut BTreeMap<UniverseId, DmxFrame>,
+}
+
+impl<'a> Attributes<'a> {
+    pub fn patch(&self) -> &Patch {
+        self.patch
+    }
+
+    /// Sets a single slot, writes to universes which do not exist are ignored.
+    pub fn set_slot(&mut self, address: DmxAddress, value: u8) {
+        if let Some(frame) = self.channels.get_mut(&address.universe) {
+            frame[address.address as usize] = value;
+        }
+    }
+
+    /// Sets the channel of a patched fixture, fixtures without the role are left alone.
+    pub fn set(&mut self, fixture: FixtureId, role: ChannelRole, value: u8) {
+        if let Some(address) = self.patch.slot(fixture, role) {
+            self.set_slot(address, value);
+        }
+    }
+
+    pub fn set_rgb(&mut self, fixture: FixtureId, [red, green, blue]: [u8; 3]) {
+        self.set(fixture, ChannelRole::Red, red);
+        self.set(fixture, ChannelRole::Green, green);
+        self.set(fixture, ChannelRole::Blue, blue);
+    }
+}
+
+/// A look which reacts to the audio analysis by writing fixture attributes.
+pub trait Effect: Send {
+    fn name(&self) -> &'static str;
+
+    /// Called for every analysis signal.
+    fn signal(&mut self, signal: Signal, ctx: &EffectContext, out: &mut Attributes<'_>);
+
+    /// Called before each signal, for changes which only depend on time.
+    fn tick(&mut self, _ctx: &EffectContext, _out: &mut Attributes<'_>) {}
+}
+
+/// Identifies an effect in the engine's stack.
+pub type EffectId = u32;
+
+/// A configured effect and its parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum EffectConfig {
+    VolumeColor(VolumeColor),
+    BeatFlash(BeatFlash),
+    BassStrobe(BassStrobe),
+    IdleColor(IdleColor),
+}
+
+impl EffectConfig {
+    pub fn build(&self) -> Box<dyn Effect> {
+        match self {
+            EffectConfig::VolumeColor(effect) => Box::new(effect.clone()),
+            EffectConfig::BeatFlash(effect) => Box::new(effect.clone()),
+            EffectConfig::BassStrobe(effect) => Box::new(effect.clone()),
+            EffectConfig::IdleColor(effect) => Box::new(effect.clone()),
+        }
+    }
+}
+
+/// An effect of the stack and the song sections it runs in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectEntry {
+    #[serde(flatten)]
+    pub effect: EffectConfig,
+    /// The effect only runs during these sections, all if empty.
+    /// Stopped effects leave their fixtures as they are, an effect below can take over.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>,
+}
+
+impl EffectEntry {
+    /// Used if no effects are configured: the looks of the default patch.
+    pub fn default_effects() -> Vec<Self> {
+        [
+            EffectConfig::VolumeColor(VolumeColor::default()),
+            EffectConfig::BeatFlash(BeatFlash::default()),
+            EffectConfig::BassStrobe(BassStrobe::default()),
+            EffectConfig::IdleColor(IdleColor::default()),
+        ]
+        .into_iter()
+        .map(Self::from)
+        .collect()
+    }
+}
+
+impl From<EffectConfig> for EffectEntry {
+    fn from(effect: EffectConfig) -> Self {
+        Self {
+            effect,
+            sections: vec![],
+        }
+    }
+}
+
+/// A named effect stack which can be switched to during the show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub name: String,
+    pub effects: Vec<EffectEntry>,
+}
+
+pub fn validate_scenes(scenes: &[Scene]) -> Result<()> {
+    for (i, scene) in scenes.iter().enumerate() {
+        if scenes[..i].iter().any(|s| s.name == scene.name) {
+            bail!("Scene `{}` is configured more than once", scene.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Commands for the effect engine, e.g. from a remote controller.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectControl {
+    /// Scales all intensities, `0.0..=1.0`.
+    Master(f32),
+    /// Switches an effect of the stack on or off.
+    Enable(EffectId, bool),
+    /// Replaces the effect stack with the scene of this name.
+    Scene(String),
+    /// Turns all intensities off while active, the master is kept.
+    Blackout(bool),
+}
+
+struct StackEntry {
+    id: EffectId,
+    /// All sections if empty.
+    sections: Vec<Section>,
+    enabled: bool,
+    effect: Box<dyn Effect>,
+}
+
+impl StackEntry {
+    fn runs_in(&self, section: Section) -> bool {
+        self.enabled && (self.sections.is_empty() || self.sections.contains(&section))
+    }
+}
+
+/// Roles which are scaled by the master if a fixture has no dimmer.
+const COLOR_ROLES: [ChannelRole; 6] = [
+    ChannelRole::Red,
+    ChannelRole::Green,
+    ChannelRole::Blue,
+    ChannelRole::White,
+    ChannelRole::Amber,
+    ChannelRole::Uv,
+];
+
+/// Runs a stack of effects on the patch, later effects win where they write the same slot.
+/// It only updates the shared frames, the DMX output thread takes care of transmitting them.
+pub struct EffectEngine {
+    frames: DmxFrames,
+    channels: BTreeMap<UniverseId, DmxFrame>,
+    patch: Patch,
+    effects: Vec<StackEntry>,
+    next_id: EffectId,
+    frame_duration: Duration,
+    section: Section,
+    scenes: Vec<Scene>,
+    master: f32,
+    blackout: bool,
+}
+
+impl EffectEngine {
+    pub fn new(frames: DmxFrames, refresh_rate: f32, patch: Patch) -> Self {
+        let mut channels = frames.snapshot();
+        {
+            let mut out = Attributes {
+                patch: &patch,
+                channels: &mut channels,
+            };
+            for (address, value) in patch.fixtures().flat_map(|f| f.defaults()) {
+                out.set_slot(address, value);
+            }
+        }
+        frames.update(&channels);
+
+        Self {
+            frames,
+            channels,
+            patch,
+            effects: vec![],
+            next_id: 0,
+            frame_duration: Duration::from_secs_f32(1.0 / refresh_rate),
+            section: Section::default(),
+            scenes: vec![],
+            master: 1.0,
+            blackout: false,
+        }
+    }
+
+    pub fn with_effects(mut self, effects: &[EffectEntry]) -> Self {
+        self.set_effects(effects);
+        self
+    }
+
+    /// Scenes which can be switched to with [`EffectControl::Scene`].
+    pub fn with_scenes(mut self, scenes: &[Scene]) -> Self {
+        self.scenes = scenes.to_vec();
+        self
+    }
+
+    /// Puts an effect on top of the stack.
+    pub fn add(&mut self, effect: Box<dyn Effect>) -> EffectId {
+        self.add_in_sections(effect, vec![])
+    }
+
+    /// Puts an effect on top of the stack which only runs during the given sections.
+    pub fn add_in_sections(&mut self, effect: Box<dyn Effect>, sections: Vec<Section>) -> EffectId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.effects.push(StackEntry {
+            id,
+            sections,
+            enabled: true,
+            effect,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: EffectId) -> Option<Box<dyn Effect>> {
+        let index = self.effects.iter().position(|entry| entry.id == id)?;
+        Some(self.effects.remove(index).effect)
+    }
+
+    /// Replaces the whole stack, e.g. after the parameters have been changed.
+    /// The effects are numbered from 0 again.
+    pub fn set_effects(&mut self, effects: &[EffectEntry]) {
+        self.effects.clear();
+        self.next_id = 0;
+        for entry in effects {
+            self.add_in_sections(entry.effect.build(), entry.sections.clone());
+        }
+    }
+
+    /// Ids and names of the effects, bottom first.
+    pub fn effects(&self) -> impl Iterator<Item = (EffectId, &'static str)> + '_ {
+        self.effects
+            .iter()
+            .map(|entry| (entry.id, entry.effect.name()))
+    }
+
+    /// The song section effects are selected by.
+    pub fn section(&self) -> Section {
+        self.section
+    }
+
+    pub fn master(&self) -> f32 {
+        self.master
+    }
+
+    pub fn control(&mut self, control: EffectControl) -> Result<()> {
+        match control {
+            EffectControl::Master(master) => {
+                if !master.is_finite() {
+                    bail!("The master level must be a number, not {master}");
+                }
+                self.master = master.clamp(0.0, 1.0);
+            }
+            EffectControl::Enable(id, enabled) => {
+                let Some(entry) = self.effects.iter_mut().find(|entry| entry.id == id) else {
+                    bail!("There is no effect {id}");
+                };
+                entry.enabled = enabled;
+            }
+            EffectControl::Blackout(blackout) => self.blackout = blackout,
+            EffectControl::Scene(name) => {
+                let Some(scene) = self.scenes.iter().find(|scene| scene.name == name) else {
+                    bail!("There is no scene `{name}`");
+                };
+                let effects = scene.effects.clone();
+                self.set_effects(&effects);
+            }
+        }
+
+        self.publish();
+        Ok(())
+    }
+
+    pub fn signal(&mut self, signal: Signal) {
+        match signal {
+            Signal::Section(state) => self.section = state.section,
+            Signal::SectionChange(change) => self.section = change.to,
+            _ => {}
+        }
+
+        let ctx = EffectContext {
+            now: Instant::now(),
+            frame_duration: self.frame_duration,
+        };
+
+        let mut out = Attributes {
+            patch: &self.patch,
+            channels: &mut self.channels,
+        };
+
+        let section = self.section;
+
+        for entry in self.effects.iter_mut().filter(|e| e.runs_in(section)) {
+            entry.effect.tick(&ctx, &mut out);
+        }
+
+        for entry in self.effects.iter_mut().filter(|e| e.runs_in(section)) {
+            entry.effect.signal(signal, &ctx, &mut out);
+        }
+
+        self.publish();
+    }
+
+    /// Hands the frames to the output thread, scaled by the master.
+    fn publish(&mut self) {
+        let master = if self.blackout { 0.0 } else { self.master };
+        if master >= 1.0 {
+            self.frames.update(&self.channels);
+            return;
+        }
+
+        let mut channels = self.channels.clone();
+        let mut out = Attributes {
+            patch: &self.patch,
+            channels: &mut channels,
+        };
+        for fixture in self.patch.fixtures() {
+            let roles: &[ChannelRole] = if fixture.mode().has_role(ChannelRole::Dimmer) {
+                &[ChannelRole::Dimmer]
+            } else {
+                &COLOR_ROLES
+            };
+
+            for address in roles.iter().filter_map(|role| fixture.slot(*role)) {
+                if let Some(frame) = self.channels.get(&address.universe) {
+                    let value = frame[address.address as usize] as f32 * master;
+                    out.set_slot(address, value.round() as u8);
+                }
+            }
+        }
+
+        self.frames.update(&channels);
+    }
+}
@@ -0,0 +1,319 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Attributes, Effect, EffectContext};
+use crate::{
+    audio::Signal,
+    fixture::{patch::FixtureId, ChannelRole},
+};
+
+/// Fixtures of the default patch.
+const WASH_FIXTURE: FixtureId = 1;
+const STROBE_FIXTURE: FixtureId = 2;
+
+/// Without a clock beat for this long, effects fall back to reacting to the raw levels.
+const TEMPO_LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the beat clock currently delivers beats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TempoLock {
+    last_beat: Option<Instant>,
+}
+
+impl TempoLock {
+    /// Returns true if the signal is a beat of the beat clock.
+    fn update(&mut self, signal: Signal, now: Instant) -> bool {
+        let is_beat = matches!(signal, Signal::Beat(_));
+        if is_beat {
+            self.last_beat = Some(now);
+        }
+        is_beat
+    }
+
+    fn locked(&self, now: Instant) -> bool {
+        self.last_beat
+            .is_some_and(|last| now.duration_since(last) < TEMPO_LOCK_TIMEOUT)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Color {
+    Red,
+    Purple,
+    Blue,
+    Cyan,
+    Green,
+    Yellow,
+}
+
+impl Color {
+    const ALL: [Self; 6] = [
+        Self::Red,
+        Self::Purple,
+        Self::Blue,
+        Self::Cyan,
+        Self::Green,
+        Self::Yellow,
+    ];
+
+    fn channels(&self) -> [u8; 3] {
+        match self {
+            Color::Red => [255, 0, 0],
+            Color::Purple => [255, 0, 255],
+            Color::Blue => [0, 0, 255],
+            Color::Cyan => [0, 255, 255],
+            Color::Green => [0, 255, 0],
+            Color::Yellow => [255, 255, 0],
+        }
+    }
+}
+
+/// Full white on all color channels and the dimmer.
+fn full_white(out: &mut Attributes<'_>, fixture: FixtureId) {
+    out.set(fixture, ChannelRole::Dimmer, 255);
+    out.set_rgb(fixture, [255, 255, 255]);
+}
+
+/// Lights the fixtures while there is a beat, cycling through colors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeColor {
+    pub fixtures: Vec<FixtureId>,
+    /// How many beats each color is held while the beat clock runs.
+    pub hold_beats: u32,
+    /// How long each color is held without the beat clock, in seconds.
+    pub hold_secs: u64,
+    #[serde(skip)]
+    color_idx: usize,
+    #[serde(skip)]
+    color_set_time: Option<Instant>,
+    #[serde(skip)]
+    beats: u32,
+    #[serde(skip)]
+    tempo: TempoLock,
+}
+
+impl Default for VolumeColor {
+    fn default() -> Self {
+        Self {
+            fixtures: vec![WASH_FIXTURE],
+            hold_beats: 16,
+            hold_secs: 10,
+            color_idx: 0,
+            color_set_time: None,
+            beats: 0,
+            tempo: TempoLock::default(),
+        }
+    }
+}
+
+impl VolumeColor {
+    fn next_color(&mut self, now: Instant) {
+        self.color_set_time = Some(now);
+        self.color_idx = (self.color_idx + 1) % Color::ALL.len();
+    }
+}
+
+impl Effect for VolumeColor {
+    fn name(&self) -> &'static str {
+        "volume_color"
+    }
+
+    fn signal(&mut self, signal: Signal, ctx: &EffectContext, out: &mut Attributes<'_>) {
+        if self.tempo.update(signal, ctx.now) {
+            self.beats += 1;
+            if self.beats >= self.hold_beats.max(1) {
+                self.beats = 0;
+                self.next_color(ctx.now);
+            }
+            return;
+        }
+
+        let Signal::BeatVolume(volume) = signal else {
+            return;
+        };
+
+        if volume <= 0.0 {
+            for &fixture in &self.fixtures {
+                out.set(fixture, ChannelRole::Dimmer, 0);
+            }
+            return;
+        }
+
+        let set_time = *self.color_set_time.get_or_insert(ctx.now);
+        if !self.tempo.locked(ctx.now)
+            && ctx.now.duration_since(set_time).as_secs() > self.hold_secs
+        {
+            self.next_color(ctx.now);
+        }
+
+        let color = Color::ALL[self.color_idx].channels();
+        for &fixture in &self.fixtures {
+            out.set(fixture, ChannelRole::Dimmer, 255);
+            out.set_rgb(fixture, color);
+        }
+    }
+}
+
+/// Flashes the fixtures white for a single frame on every beat of the beat clock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BeatFlash {
+    pub fixtures: Vec<FixtureId>,
+    #[serde(skip)]
+    flash_until: Option<Instant>,
+}
+
+impl Default for BeatFlash {
+    fn default() -> Self {
+        Self {
+            fixtures: vec![WASH_FIXTURE],
+            flash_until: None,
+        }
+    }
+}
+
+impl Effect for BeatFlash {
+    fn name(&self) -> &'static str {
+        "beat_flash"
+    }
+
+    fn tick(&mut self, ctx: &EffectContext, out: &mut Attributes<'_>) {
+        if self.flash_until.is_some_and(|until| ctx.now >= until) {
+            self.flash_until = None;
+            for &fixture in &self.fixtures {
+                out.set(fixture, ChannelRole::Dimmer, 0);
+            }
+        }
+    }
+
+    fn signal(&mut self, signal: Signal, ctx: &EffectContext, out: &mut Attributes<'_>) {
+        let Signal::Beat(_) = signal else {
+            return;
+        };
+
+        for &fixture in &self.fixtures {
+            full_white(out, fixture);
+        }
+        self.flash_until = Some(ctx.now + ctx.frame_duration);
+    }
+}
+
+/// Flashes the fixtures white on the beat while the bass is loud.
+/// Without the beat clock it fires on bass spikes, at most once per `min_interval_ms`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BassStrobe {
+    pub fixtures: Vec<FixtureId>,
+    /// Bass level above which the strobe fires, `0.0..=1.0`.
+    pub threshold: f32,
+    pub min_interval_ms: u64,
+    #[serde(skip)]
+    bass: f32,
+    #[serde(skip)]
+    last_flash: Option<Instant>,
+    #[serde(skip)]
+    flash_until: Option<Instant>,
+    #[serde(skip)]
+    tempo: TempoLock,
+}
+
+impl Default for BassStrobe {
+    fn default() -> Self {
+        Self {
+            fixtures: vec![STROBE_FIXTURE],
+            threshold: 0.4,
+            min_interval_ms: 100,
+            bass: 0.0,
+            last_flash: None,
+            flash_until: None,
+            tempo: TempoLock::default(),
+        }
+    }
+}
+
+impl Effect for BassStrobe {
+    fn name(&self) -> &'static str {
+        "bass_strobe"
+    }
+
+    fn tick(&mut self, ctx: &EffectContext, out: &mut Attributes<'_>) {
+        if self.flash_until.is_some_and(|until| ctx.now >= until) {
+            self.flash_until = None;
+            for &fixture in &self.fixtures {
+                out.set(fixture, ChannelRole::Dimmer, 0);
+            }
+        }
+    }
+
+    fn signal(&mut self, signal: Signal, ctx: &EffectContext, out: &mut Attributes<'_>) {
+        let fire = if self.tempo.update(signal, ctx.now) {
+            self.bass > self.threshold
+        } else if let Signal::Bass(level) = signal {
+            self.bass = level;
+
+            let min_interval = Duration::from_millis(self.min_interval_ms);
+            !self.tempo.locked(ctx.now)
+                && level > self.threshold
+                && self
+                    .last_flash
+                    .map_or(true, |last| ctx.now.duration_since(last) > min_interval)
+        } else {
+            false
+        };
+
+        if !fire {
+            return;
+        }
+
+        self.last_flash = Some(ctx.now);
+        for &fixture in &self.fixtures {
+            full_white(out, fixture);
+        }
+        self.flash_until = Some(ctx.now + ctx.frame_duration);
+    }
+}
+
+/// A dim color while it is (almost) silent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleColor {
+    pub fixtures: Vec<FixtureId>,
+    /// Volume below which the room counts as silent, `0.0..=1.0`.
+    pub threshold: f32,
+    pub dimmer: u8,
+    pub color: [u8; 3],
+}
+
+impl Default for IdleColor {
+    fn default() -> Self {
+        Self {
+            fixtures: vec![WASH_FIXTURE],
+            threshold: 0.05,
+            dimmer: 30,
+            color: Color::Purple.channels(),
+        }
+    }
+}
+
+impl Effect for IdleColor {
+    fn name(&self) -> &'static str {
+        "idle_color"
+    }
+
+    fn signal(&mut self, signal: Signal, _ctx: &EffectContext, out: &mut Attributes<'_>) {
+        let Signal::Volume(volume) = signal else {
+            return;
+        };
+
+        if volume >= self.threshold {
+            return;
+        }
+
+        for &fixture in &self.fixtures {
+            out.set(fixture, ChannelRole::Dimmer, self.dimmer);
+            out.set_rgb(fixture, self.color);
+        }
+    }
+}
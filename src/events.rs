@@ -0,0 +1,374 @@
+//! Publishes analysis events to other rigs (LED walls, custom controllers) over UDP.
+//!
+//! Every datagram carries a single event. The format is chosen per target:
+//!
+//! **JSON**, an object with the format version, the event type and its fields:
+//!
+//! ```text
+//! {"version":1,"type":"beat","beat":0}
+//! {"version":1,"type":"bar","bar":12}
+//! {"version":1,"type":"tempo","bpm":128.0,"confidence":0.8}
+//! {"version":1,"type":"volume","level":0.6}
+//! {"version":1,"type":"band","id":0,"energy":0.5,"peak":0.7,"onset":true}
+//! {"version":1,"type":"drop","active":true}
+//! {"version":1,"type":"section","from":"buildup","to":"drop","confidence":0.75}
+//! ```
+//!
+//! **Binary**, the magic `BL`, the format version and the event type as bytes, followed by the
+//! fields. Numbers are little endian, levels are `f32`, booleans a byte which is 0 or 1:
+//!
+//! | type | event   | fields                                                 |
+//! |------|---------|--------------------------------------------------------|
+//! | 1    | beat    | beat `u8`                                              |
+//! | 2    | bar     | bar `u8`                                               |
+//! | 3    | tempo   | bpm `f32`, confidence `f32`                            |
+//! | 4    | band    | id `u8`, energy `f32`, peak `f32`, onset `u8`          |
+//! | 5    | drop    | active `u8`                                            |
+//! | 6    | section | from `u8`, to `u8`, confidence `f32`                   |
+//! | 7    | volume  | level `f32`                                            |
+//!
+//! Sections are numbered intro 0, verse 1, buildup 2, drop 3, breakdown 4, outro 5.
+//!
+//! **OSC** messages below `/blaulicht`, e.g. `/blaulicht/beat 0`, `/blaulicht/band/0 0.5 0.7 1`
+//! or `/blaulicht/section "drop" "buildup" 0.75` (new section first), see [`crate::osc`].
+//!
+//! **Legacy** only sends the drop state as `D0` or `D1`, like earlier versions did. They sent it
+//! to `192.168.0.100:33333` without being configured, such a target has to be added now.
+//!
+//! The version is increased whenever fields change, new event types do not change it.
+
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{
+    bands::BandId,
+    sections::{Section, SectionChange},
+    Signal,
+};
+
+pub const FORMAT_VERSION: u8 = 1;
+const MAGIC: &[u8; 2] = b"BL";
+/// The lowest `max_rate` in Hz, the interval between two events has to fit a `Duration`.
+const MIN_RATE: f32 = 0.01;
+
+/// The kinds of events, used to filter what a target receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Beat,
+    Bar,
+    Tempo,
+    Volume,
+    Band,
+    Drop,
+    Section,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventFormat {
+    #[default]
+    Json,
+    Binary,
+    Osc,
+    /// `D0`/`D1` drop packets, all other events are skipped.
+    Legacy,
+}
+
+/// A receiver of events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventTarget {
+    /// E.g. `192.168.0.100:33333`.
+    pub address: SocketAddr,
+    #[serde(default)]
+    pub format: EventFormat,
+    /// The kinds of events which are sent, all if empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// Tempo, volume and band events are sent at most this often (per band), in Hz.
+    /// Beats, bars, drops and sections are never skipped.
+    #[serde(default)]
+    pub max_rate: Option<f32>,
+}
+
+pub fn validate_targets(targets: &[EventTarget]) -> Result<()> {
+    for target in targets {
+        if target
+            .max_rate
+            .is_some_and(|rate| !rate.is_finite() || rate < MIN_RATE)
+        {
+            bail!(
+                "Event target {}: `max_rate` must be at least {MIN_RATE} Hz",
+                target.address
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// An event as it is published.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A beat of the beat clock, inside the bar.
+    Beat {
+        beat: u8,
+    },
+    /// The downbeat of a bar, the bar number is wrapping.
+    Bar {
+        bar: u8,
+    },
+    Tempo {
+        bpm: f32,
+        confidence: f32,
+    },
+    Volume {
+        level: f32,
+    },
+    Band {
+        id: BandId,
+        energy: f32,
+        peak: f32,
+        onset: bool,
+    },
+    /// The drop state, sent about once per second.
+    Drop {
+        active: bool,
+    },
+    /// The song entered a new section.
+    Section {
+        from: Section,
+        to: Section,
+        confidence: f32,
+    },
+}
+
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u8,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+fn section_number(section: Section) -> u8 {
+    match section {
+        Section::Intro => 0,
+        Section::Verse => 1,
+        Section::Buildup => 2,
+        Section::Drop => 3,
+        Section::Breakdown => 4,
+        Section::Outro => 5,
+    }
+}
+
+impl Event {
+    /// The event for an analysis signal, if it is published at all.
+    pub fn from_signal(signal: Signal) -> Option<Self> {
+        let event = match signal {
+            Signal::Beat(beat) => Self::Beat { beat },
+            Signal::Bar(bar) => Self::Bar { bar },
+            Signal::Tempo(tempo) => Self::Tempo {
+                bpm: tempo.bpm,
+                confidence: tempo.confidence,
+            },
+            Signal::Volume(level) => Self::Volume { level },
+            Signal::Band {
+                id,
+                energy,
+                peak,
+                onset,
+            } => Self::Band {
+                id,
+                energy,
+                peak,
+                onset,
+            },
+            Signal::SectionChange(SectionChange {
+                from,
+                to,
+                confidence,
+            }) => Self::Section {
+                from,
+                to,
+                confidence,
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Beat { .. } => EventKind::Beat,
+            Event::Bar { .. } => EventKind::Bar,
+            Event::Tempo { .. } => EventKind::Tempo,
+            Event::Volume { .. } => EventKind::Volume,
+            Event::Band { .. } => EventKind::Band,
+            Event::Drop { .. } => EventKind::Drop,
+            Event::Section { .. } => EventKind::Section,
+        }
+    }
+
+    /// Events which are rate limited, with the key they are limited by.
+    fn rate_key(&self) -> Option<(EventKind, BandId)> {
+        match self {
+            Event::Tempo { .. } => Some((EventKind::Tempo, 0)),
+            Event::Volume { .. } => Some((EventKind::Volume, 0)),
+            Event::Band { id, .. } => Some((EventKind::Band, *id)),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(&Versioned {
+            version: FORMAT_VERSION,
+            event: self,
+        })
+        .expect("events always serialize")
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut packet = MAGIC.to_vec();
+        packet.push(FORMAT_VERSION);
+
+        match *self {
+            Event::Beat { beat } => packet.extend([1, beat]),
+            Event::Bar { bar } => packet.extend([2, bar]),
+            Event::Tempo { bpm, confidence } => {
+                packet.push(3);
+                packet.extend(bpm.to_le_bytes());
+                packet.extend(confidence.to_le_bytes());
+            }
+            Event::Volume { level } => {
+                packet.push(7);
+                packet.extend(level.to_le_bytes());
+            }
+            Event::Band {
+                id,
+                energy,
+                peak,
+                onset,
+            } => {
+                packet.extend([4, id]);
+                packet.extend(energy.to_le_bytes());
+                packet.extend(peak.to_le_bytes());
+                packet.push(onset as u8);
+            }
+            Event::Drop { active } => packet.extend([5, active as u8]),
+            Event::Section {
+                from,
+                to,
+                confidence,
+            } => {
+                packet.extend([6, section_number(from), section_number(to)]);
+                packet.extend(confidence.to_le_bytes());
+            }
+        }
+
+        packet
+    }
+
+    fn to_legacy(self) -> Option<Vec<u8>> {
+        match self {
+            Event::Drop { active } => Some(vec![b'D', if active { b'1' } else { b'0' }]),
+            _ => None,
+        }
+    }
+}
+
+struct TargetState {
+    config: EventTarget,
+    last_sent: HashMap<(EventKind, BandId), Instant>,
+    /// Send errors are only logged once until a packet goes through again.
+    failing: bool,
+}
+
+/// Sends events to the configured targets.
+pub struct EventPublisher {
+    socket: Option<UdpSocket>,
+    targets: Vec<TargetState>,
+}
+
+impl EventPublisher {
+    pub fn new(targets: &[EventTarget]) -> Result<Self> {
+        // Without targets there is no need for a socket.
+        let socket = if targets.is_empty() {
+            None
+        } else {
+            let socket = UdpSocket::bind("0.0.0.0:0").context("Could not bind the event socket")?;
+            // Required if a target is a (directed) broadcast address.
+            socket
+                .set_broadcast(true)
+                .context("Could not enable broadcasts on the event socket")?;
+            Some(socket)
+        };
+
+        Ok(Self {
+            socket,
+            targets: targets
+                .iter()
+                .cloned()
+                .map(|config| TargetState {
+                    config,
+                    last_sent: HashMap::new(),
+                    failing: false,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn publish(&mut self, now: Instant, event: &Event) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        for target in &mut self.targets {
+            let config = &target.config;
+            if !config.events.is_empty() && !config.events.contains(&event.kind()) {
+                continue;
+            }
+
+            if let (Some(rate), Some(key)) = (config.max_rate, event.rate_key()) {
+                let interval = Duration::from_secs_f32(1.0 / rate);
+                if target
+                    .last_sent
+                    .get(&key)
+                    .is_some_and(|last| now.saturating_duration_since(*last) < interval)
+                {
+                    continue;
+                }
+                target.last_sent.insert(key, now);
+            }
+
+            let packet = match config.format {
+                EventFormat::Json => event.to_json(),
+                EventFormat::Binary => event.to_binary(),
+                EventFormat::Osc => crate::osc::event_message(event).encode(),
+                EventFormat::Legacy => match event.to_legacy() {
+                    Some(packet) => packet,
+                    None => continue,
+                },
+            };
+
+            match socket.send_to(&packet, config.address) {
+                Ok(_) => target.failing = false,
+                Err(err) if !target.failing => {
+                    warn!("[events] Could not send to {}: {err}", config.address);
+                    target.failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,154 @@
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use super::{validate_targets, Event, EventFormat, EventKind, EventPublisher, EventTarget};
+use crate::audio::sections::Section;
+
+/// One event of every type, with values which are exact as `f32`.
+fn events() -> Vec<Event> {
+    vec![
+        Event::Beat { beat: 3 },
+        Event::Bar { bar: 12 },
+        Event::Tempo {
+            bpm: 128.0,
+            confidence: 0.75,
+        },
+        Event::Volume { level: 0.5 },
+        Event::Band {
+            id: 2,
+            energy: 0.5,
+            peak: 0.75,
+            onset: true,
+        },
+        Event::Drop { active: true },
+        Event::Section {
+            from: Section::Buildup,
+            to: Section::Drop,
+            confidence: 0.25,
+        },
+    ]
+}
+
+#[test]
+fn binary_layout_is_stable() {
+    let expected: [&[u8]; 7] = [
+        b"BL\x01\x01\x03",
+        b"BL\x01\x02\x0c",
+        b"BL\x01\x03\x00\x00\x00\x43\x00\x00\x40\x3f",
+        b"BL\x01\x07\x00\x00\x00\x3f",
+        b"BL\x01\x04\x02\x00\x00\x00\x3f\x00\x00\x40\x3f\x01",
+        b"BL\x01\x05\x01",
+        b"BL\x01\x06\x02\x03\x00\x00\x80\x3e",
+    ];
+
+    for (event, expected) in events().iter().zip(expected) {
+        assert_eq!(event.to_binary(), expected, "{event:?}");
+    }
+}
+
+#[test]
+fn json_layout_is_stable() {
+    let expected = [
+        r#"{"version":1,"type":"beat","beat":3}"#,
+        r#"{"version":1,"type":"bar","bar":12}"#,
+        r#"{"version":1,"type":"tempo","bpm":128.0,"confidence":0.75}"#,
+        r#"{"version":1,"type":"volume","level":0.5}"#,
+        r#"{"version":1,"type":"band","id":2,"energy":0.5,"peak":0.75,"onset":true}"#,
+        r#"{"version":1,"type":"drop","active":true}"#,
+        r#"{"version":1,"type":"section","from":"buildup","to":"drop","confidence":0.25}"#,
+    ];
+
+    for (event, expected) in events().iter().zip(expected) {
+        assert_eq!(String::from_utf8(event.to_json()).unwrap(), expected);
+    }
+}
+
+#[test]
+fn legacy_only_sends_drops() {
+    for event in events() {
+        let expected = match event {
+            Event::Drop { .. } => Some(b"D1".to_vec()),
+            _ => None,
+        };
+        assert_eq!(event.to_legacy(), expected, "{event:?}");
+    }
+    assert_eq!(
+        Event::Drop { active: false }.to_legacy(),
+        Some(b"D0".to_vec())
+    );
+}
+
+fn target(max_rate: Option<f32>) -> EventTarget {
+    EventTarget {
+        address: ([127, 0, 0, 1], 33333).into(),
+        format: EventFormat::Json,
+        events: vec![],
+        max_rate,
+    }
+}
+
+#[test]
+fn max_rate_has_a_lower_bound() {
+    assert!(validate_targets(&[target(None), target(Some(0.01)), target(Some(40.0))]).is_ok());
+
+    for rate in [0.0, -1.0, 1e-30, f32::NAN, f32::INFINITY] {
+        assert!(
+            validate_targets(&[target(Some(rate))]).is_err(),
+            "{rate} was accepted"
+        );
+    }
+}
+
+#[test]
+fn targets_get_their_kinds_at_their_rate() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let target = EventTarget {
+        address: receiver.local_addr().unwrap(),
+        format: EventFormat::Binary,
+        events: vec![EventKind::Volume, EventKind::Drop],
+        max_rate: Some(10.0),
+    };
+    let mut publisher = EventPublisher::new(&[target]).unwrap();
+
+    let start = Instant::now();
+    let volume = Event::Volume { level: 0.5 };
+    publisher.publish(start, &volume);
+    // Filtered by kind.
+    publisher.publish(start, &Event::Beat { beat: 0 });
+    // Within 100ms of the last volume event.
+    publisher.publish(start + Duration::from_millis(50), &volume);
+    // Never rate limited.
+    publisher.publish(
+        start + Duration::from_millis(50),
+        &Event::Drop { active: true },
+    );
+    publisher.publish(start + Duration::from_millis(150), &volume);
+
+    let mut received = vec![];
+    let mut buffer = [0; 64];
+    while let Ok(len) = receiver.recv(&mut buffer) {
+        received.push(buffer[..len].to_vec());
+    }
+
+    assert_eq!(
+        received,
+        vec![
+            volume.to_binary(),
+            Event::Drop { active: true }.to_binary(),
+            volume.to_binary(),
+        ]
+    );
+}
+
+#[test]
+fn broadcast_targets_can_be_sent_to() {
+    let publisher = EventPublisher::new(&[target(None)]).unwrap();
+    assert!(publisher.socket.unwrap().broadcast().unwrap());
+
+    assert!(EventPublisher::new(&[]).unwrap().socket.is_none());
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+pub mod patch;
+
+/// Profiles which are always available, user profiles with the same name replace them.
+const BUILTIN_PROFILES: [&str; 5] = [
+    include_str!("../assets/fixtures/generic_dimmer.toml"),
+    include_str!("../assets/fixtures/generic_rgb_par.toml"),
+    include_str!("../assets/fixtures/generic_rgbw_par.toml"),
+    include_str!("../assets/fixtures/generic_strobe.toml"),
+    include_str!("../assets/fixtures/generic_moving_head.toml"),
+];
+
+/// What a fixture channel controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelRole {
+    Dimmer,
+    Red,
+    Green,
+    Blue,
+    White,
+    Amber,
+    Uv,
+    Strobe,
+    Pan,
+    Tilt,
+    Speed,
+    Macro,
+    /// Anything else, identified by the channel name.
+    Generic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureChannel {
+    pub role: ChannelRole,
+    /// Lower byte of a 16-bit role, the coarse channel has the same role without `fine`.
+    #[serde(default)]
+    pub fine: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Value of the channel if no effect sets it.
+    #[serde(default)]
+    pub default: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureMode {
+    pub name: String,
+    pub channels: Vec<FixtureChannel>,
+}
+
+impl FixtureMode {
+    /// Number of DMX slots the mode occupies.
+    pub fn footprint(&self) -> u16 {
+        self.channels.len() as u16
+    }
+
+    fn offset(&self, role: ChannelRole, fine: bool) -> Option<u16> {
+        self.channels
+            .iter()
+            .position(|c| c.role == role && c.fine == fine)
+            .map(|offset| offset as u16)
+    }
+
+    /// Offset of the (coarse) channel for `role` relative to the start address.
+    pub fn channel(&self, role: ChannelRole) -> Option<u16> {
+        self.offset(role, false)
+    }
+
+    /// Offset of the fine channel for `role`, if the mode has 16-bit resolution for it.
+    pub fn fine_channel(&self, role: ChannelRole) -> Option<u16> {
+        self.offset(role, true)
+    }
+
+    /// Offset of a `Generic` channel by name.
+    pub fn named_channel(&self, name: &str) -> Option<u16> {
+        self.channels
+            .iter()
+            .position(|c| c.name.as_deref() == Some(name))
+            .map(|offset| offset as u16)
+    }
+
+    pub fn has_role(&self, role: ChannelRole) -> bool {
+        self.channel(role).is_some()
+    }
+
+    pub fn defaults(&self) -> Vec<u8> {
+        self.channels.iter().map(|c| c.default).collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.channels.is_empty() || self.channels.len() > 512 {
+            bail!(
+                "mode `{}` must have between 1 and 512 channels, has {}",
+                self.name,
+                self.channels.len()
+            );
+        }
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            if channel.role == ChannelRole::Generic {
+                if channel.name.is_none() {
+                    bail!(
+                        "mode `{}`: generic channel {} needs a name",
+                        self.name,
+                        i + 1
+                    );
+                }
+                continue;
+            }
+
+            if self.offset(channel.role, channel.fine) != Some(i as u16) {
+                bail!(
+                    "mode `{}`: role {:?}{} is assigned more than once",
+                    self.name,
+                    channel.role,
+                    if channel.fine { " (fine)" } else { "" }
+                );
+            }
+
+            if channel.fine && !self.has_role(channel.role) {
+                bail!(
+                    "mode `{}`: fine channel for {:?} without a coarse channel",
+                    self.name,
+                    channel.role
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureProfile {
+    pub name: String,
+    #[serde(default)]
+    pub manufacturer: String,
+    pub modes: Vec<FixtureMode>,
+}
+
+impl FixtureProfile {
+    pub fn parse(content: &str) -> Result<Self> {
+        let profile: Self = toml::from_str(content)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn mode(&self, name: &str) -> Option<&FixtureMode> {
+        self.modes.iter().find(|mode| mode.name == name)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.modes.is_empty() {
+            bail!("profile `{}` has no modes", self.name);
+        }
+
+        for (i, mode) in self.modes.iter().enumerate() {
+            if self.modes[..i].iter().any(|m| m.name == mode.name) {
+                bail!(
+                    "profile `{}`: mode `{}` is defined twice",
+                    self.name,
+                    mode.name
+                );
+            }
+
+            mode.validate()
+                .with_context(|| format!("Invalid profile `{}`", self.name))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// All known fixture profiles, by name.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureLibrary {
+    profiles: BTreeMap<String, FixtureProfile>,
+}
+
+impl FixtureLibrary {
+    pub fn builtin() -> Self {
+        let mut library = Self::default();
+
+        for content in BUILTIN_PROFILES {
+            let profile = FixtureProfile::parse(content).expect("built-in profiles are valid");
+            library.add(profile);
+        }
+
+        library
+    }
+
+    /// Built-in profiles plus every `*.toml` file in `dir` (if it exists).
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut library = Self::builtin();
+
+        if !dir.is_dir() {
+            debug!("No fixture profile directory at {}", dir.to_string_lossy());
+            return Ok(library);
+        }
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        paths.sort();
+
+        for path in paths {
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            let content = fs::read_to_string(&path)?;
+            let profile = FixtureProfile::parse(&content).with_context(|| {
+                format!("Failed to load fixture profile {}", path.to_string_lossy())
+            })?;
+
+            info!(
+                "Loaded fixture profile `{}` from {}",
+                profile.name,
+                path.to_string_lossy()
+            );
+            library.add(profile);
+        }
+
+        Ok(library)
+    }
+
+    pub fn add(&mut self, profile: FixtureProfile) {
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FixtureProfile> {
+        self.profiles.get(name)
+    }
+
+    pub fn mode(&self, profile: &str, mode: &str) -> Option<&FixtureMode> {
+        self.get(profile)?.mode(mode)
+    }
+
+    pub fn profiles(&self) -> impl Iterator<Item = &FixtureProfile> {
+        self.profiles.values()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{ChannelRole, FixtureLibrary, FixtureMode};
+use crate::dmx::universe::{DmxAddress, UniverseId};
+
+/// Identifies a patched fixture, stable across renames.
+pub type FixtureId = u16;
+
+/// A fixture instance as stored in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureConfig {
+    pub id: FixtureId,
+    pub name: String,
+    /// Name of a profile in the fixture library.
+    pub profile: String,
+    pub mode: String,
+    pub universe: UniverseId,
+    /// Start address, `1..=512`.
+    pub address: u16,
+}
+
+impl FixtureConfig {
+    /// Used if no patch is configured: a wash PAR at 1 and a strobe PAR at 10 in universe 1.
+    pub fn default_patch() -> Vec<Self> {
+        vec![
+            Self {
+                id: 1,
+                name: "Wash".to_string(),
+                profile: "Generic RGB PAR".to_string(),
+                mode: "4ch".to_string(),
+                universe: 1,
+                address: 1,
+            },
+            Self {
+                id: 2,
+                name: "Strobe".to_string(),
+                profile: "Generic RGB PAR".to_string(),
+                mode: "4ch".to_string(),
+                universe: 1,
+                address: 10,
+            },
+        ]
+    }
+}
+
+/// A fixture whose profile has been resolved and whose footprint fits the universe.
+#[derive(Debug, Clone)]
+pub struct PatchedFixture {
+    config: FixtureConfig,
+    mode: FixtureMode,
+}
+
+impl PatchedFixture {
+    pub fn id(&self) -> FixtureId {
+        self.config.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn config(&self) -> &FixtureConfig {
+        &self.config
+    }
+
+    pub fn mode(&self) -> &FixtureMode {
+        &self.mode
+    }
+
+    pub fn start(&self) -> DmxAddress {
+        self.absolute(0)
+    }
+
+    /// Last slot occupied by the fixture.
+    pub fn end(&self) -> DmxAddress {
+        self.absolute(self.mode.footprint() - 1)
+    }
+
+    fn absolute(&self, offset: u16) -> DmxAddress {
+        DmxAddress {
+            universe: self.config.universe,
+            address: self.config.address + offset,
+        }
+    }
+
+    /// Absolute slot of the (coarse) channel for `role`.
+    pub fn slot(&self, role: ChannelRole) -> Option<DmxAddress> {
+        self.mode.channel(role).map(|offset| self.absolute(offset))
+    }
+
+    pub fn fine_slot(&self, role: ChannelRole) -> Option<DmxAddress> {
+        self.mode
+            .fine_channel(role)
+            .map(|offset| self.absolute(offset))
+    }
+
+    pub fn named_slot(&self, name: &str) -> Option<DmxAddress> {
+        self.mode
+            .named_channel(name)
+            .map(|offset| self.absolute(offset))
+    }
+
+    /// All slots of the fixture with their default values.
+    pub fn defaults(&self) -> impl Iterator<Item = (DmxAddress, u8)> + '_ {
+        self.mode
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(offset, channel)| (self.absolute(offset as u16), channel.default))
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.config.universe == other.config.universe
+            && self.start().address <= other.end().address
+            && other.start().address <= self.end().address
+    }
+}
+
+impl std::fmt::Display for PatchedFixture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} `{}` ({}-{})",
+            self.config.id,
+            self.config.name,
+            self.start(),
+            self.end()
+        )
+    }
+}
+
+/// All fixtures in the rig, maps a fixture's roles to absolute DMX slots.
+#[derive(Debug, Clone, Default)]
+pub struct Patch {
+    fixtures: BTreeMap<FixtureId, PatchedFixture>,
+}
+
+impl Patch {
+    pub fn new(
+        fixtures: &[FixtureConfig],
+        library: &FixtureLibrary,
+        universes: &[UniverseId],
+    ) -> Result<Self> {
+        let mut patch = Self::default();
+        for fixture in fixtures {
+            patch.add(fixture.clone(), library, universes)?;
+        }
+
+        Ok(patch)
+    }
+
+    /// Patches a fixture, rejecting unknown profiles, bad addresses and overlapping footprints.
+    pub fn add(
+        &mut self,
+        fixture: FixtureConfig,
+        library: &FixtureLibrary,
+        universes: &[UniverseId],
+    ) -> Result<()> {
+        if self.fixtures.contains_key(&fixture.id) {
+            bail!("Fixture id {} is patched more than once", fixture.id);
+        }
+
+        if !universes.contains(&fixture.universe) {
+            bail!(
+                "Fixture #{} `{}` is patched to universe {}, which is not configured",
+                fixture.id,
+                fixture.name,
+                fixture.universe
+            );
+        }
+
+        let Some(profile) = library.get(&fixture.profile) else {
+            bail!(
+                "Fixture #{} `{}` uses unknown profile `{}`",
+                fixture.id,
+                fixture.name,
+                fixture.profile
+            );
+        };
+
+        let Some(mode) = profile.mode(&fixture.mode) else {
+            bail!(
+                "Fixture #{} `{}`: profile `{}` has no mode `{}`",
+                fixture.id,
+                fixture.name,
+                fixture.profile,
+                fixture.mode
+            );
+        };
+
+        if !(1..=512).contains(&fixture.address) {
+            bail!(
+                "Fixture #{} `{}`: start address {} is out of range (1..=512)",
+                fixture.id,
+                fixture.name,
+                fixture.address
+            );
+        }
+
+        let end = fixture.address as u32 + mode.footprint() as u32 - 1;
+        if end > 512 {
+            bail!(
+                "Fixture #{} `{}`: {} channels starting at {} end at {end}, beyond slot 512",
+                fixture.id,
+                fixture.name,
+                mode.footprint(),
+                fixture.address
+            );
+        }
+
+        let patched = PatchedFixture {
+            config: fixture,
+            mode: mode.clone(),
+        };
+
+        if let Some(other) = self
+            .fixtures
+            .values()
+            .find(|other| other.overlaps(&patched))
+        {
+            bail!("Fixture {patched} overlaps fixture {other}");
+        }
+
+        self.fixtures.insert(patched.id(), patched);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: FixtureId) -> Option<PatchedFixture> {
+        self.fixtures.remove(&id)
+    }
+
+    pub fn get(&self, id: FixtureId) -> Option<&PatchedFixture> {
+        self.fixtures.get(&id)
+    }
+
+    /// Absolute slot of `role` on fixture `id`, if the fixture exists and has the role.
+    pub fn slot(&self, id: FixtureId, role: ChannelRole) -> Option<DmxAddress> {
+        self.get(id)?.slot(role)
+    }
+
+    pub fn fixtures(&self) -> impl Iterator<Item = &PatchedFixture> {
+        self.fixtures.values()
+    }
+
+    /// Fixtures which have a channel for `role`.
+    pub fn with_role(&self, role: ChannelRole) -> impl Iterator<Item = &PatchedFixture> {
+        self.fixtures
+            .values()
+            .filter(move |fixture| fixture.mode.has_role(role))
+    }
+
+    pub fn configs(&self) -> Vec<FixtureConfig> {
+        self.fixtures().map(|f| f.config.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests;
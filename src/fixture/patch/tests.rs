@@ -0,0 +1,116 @@
+use super::{FixtureConfig, Patch};
+use crate::{
+    dmx::universe::{DmxAddress, UniverseId},
+    fixture::{ChannelRole, FixtureLibrary},
+};
+
+const UNIVERSES: &[UniverseId] = &[1, 2];
+
+fn fixture(
+    id: u16,
+    profile: &str,
+    mode: &str,
+    universe: UniverseId,
+    address: u16,
+) -> FixtureConfig {
+    FixtureConfig {
+        id,
+        name: format!("Fixture {id}"),
+        profile: profile.to_string(),
+        mode: mode.to_string(),
+        universe,
+        address,
+    }
+}
+
+fn par(id: u16, universe: UniverseId, address: u16) -> FixtureConfig {
+    fixture(id, "Generic RGB PAR", "4ch", universe, address)
+}
+
+fn patch(fixtures: &[FixtureConfig]) -> anyhow::Result<Patch> {
+    Patch::new(fixtures, &FixtureLibrary::builtin(), UNIVERSES)
+}
+
+fn address(universe: UniverseId, address: u16) -> DmxAddress {
+    DmxAddress { universe, address }
+}
+
+fn error(fixtures: &[FixtureConfig]) -> String {
+    format!("{:#}", patch(fixtures).unwrap_err())
+}
+
+#[test]
+fn roles_map_to_absolute_slots() {
+    let patch = patch(&[
+        par(1, 1, 10),
+        fixture(2, "Generic Moving Head", "11ch", 2, 100),
+    ])
+    .unwrap();
+
+    let wash = patch.get(1).unwrap();
+    assert_eq!(wash.start(), address(1, 10));
+    assert_eq!(wash.end(), address(1, 13));
+    assert_eq!(wash.slot(ChannelRole::Dimmer), Some(address(1, 10)));
+    assert_eq!(wash.slot(ChannelRole::Blue), Some(address(1, 13)));
+    assert_eq!(wash.slot(ChannelRole::Pan), None);
+    assert_eq!(patch.slot(1, ChannelRole::Red), Some(address(1, 11)));
+
+    let head = patch.get(2).unwrap();
+    assert_eq!(head.slot(ChannelRole::Pan), Some(address(2, 100)));
+    assert_eq!(head.fine_slot(ChannelRole::Pan), Some(address(2, 101)));
+    assert_eq!(head.fine_slot(ChannelRole::Dimmer), None);
+    assert_eq!(head.slot(ChannelRole::White), Some(address(2, 110)));
+    assert_eq!(
+        head.defaults().take(2).collect::<Vec<_>>(),
+        vec![(address(2, 100), 128), (address(2, 101), 0)]
+    );
+
+    let with_pan: Vec<_> = patch.with_role(ChannelRole::Pan).map(|f| f.id()).collect();
+    assert_eq!(with_pan, vec![2]);
+}
+
+#[test]
+fn overlapping_footprints_are_rejected() {
+    let message = error(&[par(1, 1, 1), fixture(2, "Generic RGB PAR", "3ch", 1, 4)]);
+    assert!(message.contains("overlaps"), "{message}");
+
+    // Adjacent, or in another universe.
+    assert!(patch(&[par(1, 1, 1), par(2, 1, 5), par(3, 2, 1)]).is_ok());
+}
+
+#[test]
+fn footprints_end_at_slot_512() {
+    assert_eq!(
+        patch(&[par(1, 1, 509)]).unwrap().get(1).unwrap().end(),
+        address(1, 512)
+    );
+
+    let message = error(&[par(1, 1, 510)]);
+    assert!(message.contains("beyond slot 512"), "{message}");
+
+    for start in [0, 513] {
+        let message = error(&[par(1, 1, start)]);
+        assert!(message.contains("out of range"), "{message}");
+    }
+}
+
+#[test]
+fn unknown_references_are_rejected() {
+    let message = error(&[par(1, 3, 1)]);
+    assert!(
+        message.contains("universe 3, which is not configured"),
+        "{message}"
+    );
+
+    let message = error(&[fixture(1, "Generic RGB PAR", "5ch", 1, 1)]);
+    assert!(message.contains("has no mode `5ch`"), "{message}");
+
+    let message = error(&[fixture(1, "Fog Machine", "1ch", 1, 1)]);
+    assert!(
+        message.contains("unknown profile `Fog Machine`"),
+        "{message}"
+    );
+
+    let message = error(&[par(1, 1, 1), par(1, 2, 1)]);
+    assert!(message.contains("more than once"), "{message}");
+}
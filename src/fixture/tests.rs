@@ -0,0 +1,136 @@
+use std::{env, fs, process};
+
+use super::{ChannelRole, FixtureLibrary, FixtureProfile};
+
+/// A profile with a single mode of the given channels, in TOML.
+fn profile(channels: &str) -> String {
+    format!(
+        r#"
+        name = "Test"
+
+        [[modes]]
+        name = "test"
+        channels = [{channels}]
+        "#
+    )
+}
+
+fn error(content: &str) -> String {
+    format!("{:#}", FixtureProfile::parse(content).unwrap_err())
+}
+
+#[test]
+fn builtin_profiles_are_valid() {
+    let library = FixtureLibrary::builtin();
+    assert_eq!(library.profiles().count(), 5);
+
+    let mode = library.mode("Generic Dimmer", "2ch").unwrap();
+    assert_eq!(mode.channel(ChannelRole::Dimmer), Some(0));
+    assert_eq!(mode.fine_channel(ChannelRole::Dimmer), Some(1));
+}
+
+#[test]
+fn roles_are_assigned_once() {
+    let message = error(&profile(r#"{ role = "red" }, { role = "red" }"#));
+    assert!(
+        message.contains("role Red is assigned more than once"),
+        "{message}"
+    );
+
+    let message = error(&profile(
+        r#"{ role = "pan" }, { role = "pan", fine = true }, { role = "pan", fine = true }"#,
+    ));
+    assert!(
+        message.contains("Pan (fine) is assigned more than once"),
+        "{message}"
+    );
+
+    // Generic channels are told apart by their name.
+    let profile = FixtureProfile::parse(&profile(
+        r#"{ role = "generic", name = "Gobo" }, { role = "generic", name = "Prism" }"#,
+    ))
+    .unwrap();
+    assert_eq!(profile.modes[0].named_channel("Prism"), Some(1));
+}
+
+#[test]
+fn fine_channels_need_a_coarse_channel() {
+    let message = error(&profile(r#"{ role = "tilt", fine = true }"#));
+    assert!(
+        message.contains("fine channel for Tilt without a coarse channel"),
+        "{message}"
+    );
+
+    // The fine channel may come first.
+    assert!(FixtureProfile::parse(&profile(
+        r#"{ role = "tilt", fine = true }, { role = "tilt" }"#
+    ))
+    .is_ok());
+}
+
+#[test]
+fn generic_channels_need_a_name() {
+    let message = error(&profile(r#"{ role = "dimmer" }, { role = "generic" }"#));
+    assert!(
+        message.contains("generic channel 2 needs a name"),
+        "{message}"
+    );
+}
+
+#[test]
+fn modes_have_unique_names_and_channels() {
+    let content = r#"
+        name = "Test"
+
+        [[modes]]
+        name = "1ch"
+        channels = [{ role = "dimmer" }]
+
+        [[modes]]
+        name = "1ch"
+        channels = [{ role = "strobe" }]
+    "#;
+    let message = error(content);
+    assert!(message.contains("mode `1ch` is defined twice"), "{message}");
+
+    let message = error(&profile(""));
+    assert!(
+        message.contains("between 1 and 512 channels, has 0"),
+        "{message}"
+    );
+
+    let message = error(r#"name = "Test""#);
+    assert!(message.contains("modes"), "{message}");
+}
+
+#[test]
+fn user_profiles_replace_builtin_ones() {
+    let dir = env::temp_dir().join(format!("blaulicht-fixtures-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("dimmer.toml"),
+        r#"
+        name = "Generic Dimmer"
+
+        [[modes]]
+        name = "3ch"
+        channels = [{ role = "dimmer" }, { role = "dimmer", fine = true }, { role = "strobe" }]
+        "#,
+    )
+    .unwrap();
+    fs::write(dir.join("notes.txt"), "not a profile").unwrap();
+
+    let library = FixtureLibrary::load(&dir);
+    let missing = FixtureLibrary::load(&dir.join("missing"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let library = library.unwrap();
+    assert_eq!(library.profiles().count(), 5);
+    let dimmer = library.get("Generic Dimmer").unwrap();
+    assert_eq!(dimmer.modes.len(), 1);
+    assert_eq!(dimmer.modes[0].footprint(), 3);
+    assert!(library.get("Generic RGB PAR").is_some());
+
+    // Without a directory only the built-in profiles are used.
+    assert_eq!(missing.unwrap().profiles().count(), 5);
+}
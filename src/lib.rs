@@ -2,7 +2,15 @@
 
 pub mod app;
 pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
 pub mod dmx;
+pub mod effect;
+pub mod events;
+pub mod fixture;
+pub mod osc;
+pub mod remote;
+pub mod supervisor;
 pub mod utils;
 pub mod config;
 pub use app::BlaulichtApp;
@@ -1,91 +1,169 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
-                                                                   //
-use anyhow::anyhow;
-use blaulicht::audio::AudioThreadControlSignal;
-use blaulicht::dmx;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> anyhow::Result<()> {
     use std::{
-        net::UdpSocket, sync::{atomic::AtomicU8, mpsc, Arc}, thread
+        sync::{atomic::AtomicU8, Arc},
+        thread,
     };
 
-    use anyhow::bail;
-    use blaulicht::{app, config};
-    // Log to stderr (if you run with `RUST_LOG=debug`).
-    use egui::TextBuffer;
-    env_logger::init();
+    use blaulicht::{
+        app,
+        audio::{AnalysisContext, AudioThreadControlSignal},
+        cli::{self, Cli, Command},
+        config, dmx,
+        fixture::{patch::Patch, FixtureLibrary},
+        osc,
+        remote::server,
+        supervisor::{Supervisor, Worker},
+    };
+    use clap::Parser as _;
+
+    let cli = Cli::parse();
+
+    // Log to stderr, `RUST_LOG=debug` or `--log-level debug` for more.
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    if let Some(Command::ListDevices) = cli.command {
+        cli::list_devices();
+        return Ok(());
+    }
 
     // Load config
-    let config_path = config::config_path()?;
-    let Some(config) = config::read_config(config_path.clone())? else {
-        eprintln!(
-            "Created default config file at `{}`",
-            config_path.to_string_lossy()
-        );
+    let config_path = config::config_path(cli.config.clone())?;
+    let mut config = config::read_config(config_path.clone())?;
+
+    if let Some(Command::ListPorts) = cli.command {
+        cli::list_ports(&config);
         return Ok(());
-    };
+    }
 
-    let (from_frontend_sender, from_frontend_receiver) = crossbeam_channel::unbounded();
-    // let (signal_out, signal_receiver) = crossbeam_channel::unbounded();
+    cli::override_outputs(&mut config, &cli.dmx)?;
+    if let Some(query) = &cli.audio {
+        cli::override_audio_device(&mut config, query)?;
+    }
 
-    // let (dmx_signal_out, dmx_signal_receiver) = crossbeam_channel::unbounded();
-    let (app_signal_out, app_signal_receiver) = crossbeam_channel::unbounded();
+    let fixtures = FixtureLibrary::load(&config::fixtures_dir(&config_path))?;
+    let universe_ids: Vec<_> = config.universes.iter().map(|u| u.id).collect();
+    let patch = Patch::new(&config.patch, &fixtures, &universe_ids)?;
 
-    // {
-    // Fanout thread.
-    // TODO: remove this in the long run, this will add latency.
-    // thread::spawn(move || loop {
-    //     let s = signal_receiver.recv().unwrap();
-    //     dmx_signal_out.send(s).unwrap();
-    //     app_signal_out.send(s).unwrap();
-    // });
-    // }
+    let (from_frontend_sender, from_frontend_receiver) = crossbeam_channel::unbounded();
+    let (audio_control_sender, audio_control_receiver) = crossbeam_channel::unbounded();
+    let (mut app_signal_out, app_signal_receiver) = crossbeam_channel::unbounded();
+
+    let (mut system_out, system_receiver) = crossbeam_channel::unbounded();
+    let (dmx_control_sender, dmx_control_receiver) = crossbeam_channel::unbounded();
+
+    if let Some(remote_config) = config.remote.clone() {
+        // Remote API thread, it streams everything the GUI receives.
+        let subscribers = server::Subscribers::default();
+        app_signal_out = server::forward(app_signal_out, subscribers.clone());
+        system_out = server::forward(system_out, subscribers.clone());
+
+        let config = config.clone();
+        let from_frontend = from_frontend_sender.clone();
+        let audio_control = audio_control_sender.clone();
+        let dmx_control = dmx_control_sender.clone();
+        let system_out = system_out.clone();
+        thread::spawn(move || {
+            server::remote_thread(
+                remote_config,
+                config,
+                from_frontend,
+                audio_control,
+                dmx_control,
+                subscribers,
+                system_out,
+            )
+        });
+    }
+    // No analysis runs until the audio thread starts one.
+    let audio_thread_control_signal = Arc::new(AtomicU8::new(AudioThreadControlSignal::DEAD));
+    let dmx_frames = dmx::universe::DmxFrames::new(universe_ids);
 
-    let (system_out, _system_receiver) = crossbeam_channel::unbounded();
-    let audio_thread_control_signal = Arc::new(AtomicU8::new(AudioThreadControlSignal::CONTINUE));
+    // Restarts the audio, analysis and DMX threads when they fail.
+    let mut supervisor = Supervisor::new(system_out.clone());
 
     {
         // Audio recording and analysis thread.
+        let context = AnalysisContext {
+            signal_out: app_signal_out,
+            system_out: system_out.clone(),
+            thread_control_signal: audio_thread_control_signal.clone(),
+            audio_control: audio_control_receiver,
+            app_config: config.clone(),
+            dmx_frames: dmx_frames.clone(),
+            patch,
+        };
+        supervisor.spawn(
+            Worker::Audio,
+            || false,
+            move || dmx::audio_thread(from_frontend_receiver.clone(), context.clone()),
+        )?;
+    }
+
+    if let Some(osc_config) = config.osc.clone() {
+        // OSC control thread.
+        let from_frontend = from_frontend_sender.clone();
+        let audio_control = audio_control_sender.clone();
         let system_out = system_out.clone();
-        let audio_thread_control_signal = audio_thread_control_signal.clone();
-        thread::spawn(|| {
-            dmx::audio_thread(
-                from_frontend_receiver,
-                audio_thread_control_signal,
-                app_signal_out,
-                system_out,
-            )
+        thread::spawn(move || {
+            osc::osc_thread(osc_config, from_frontend, audio_control, system_out)
         });
     }
 
-    // let (dmx_control_sender, dmx_control_receiver) = crossbeam_channel::unbounded();
-
-    // {
-    // DMX thread.
-    // thread::spawn(move || {
-    //     dmx::dmx_thread(dmx_control_receiver, dmx_signal_receiver, system_out)
-    // });
-    // }
-
-    // {
-    //     let socket = UdpSocket::bind("0.0.0.0:5005")?;
-    //
-    //     // Receives a single datagram message on the socket. If `buf` is too small to hold
-    //     // the message, it will be cut off.
-    //     let mut buf = [0; 10];
-    //     let (amt, src) = socket.recv_from(&mut buf)?;
-    //
-    //     audio_thread_control_signal.store(, Ordering::Relaxed);
-    //
-    //     // Redeclare `buf` as slice of the received data and send reverse data back to origin.
-    //     // let buf = &mut buf[..amt];
-    //     // buf.reverse();
-    //     // socket.send_to(buf, &src)?;
-    // }
+    {
+        // DMX output thread, it stops after `Shutdown`.
+        let system_out = system_out.clone();
+        let config = config.clone();
+        supervisor.spawn(
+            Worker::Dmx,
+            || false,
+            move || {
+                dmx::dmx_thread(
+                    dmx_control_receiver.clone(),
+                    dmx_frames.clone(),
+                    system_out.clone(),
+                    config.clone(),
+                )
+            },
+        )?;
+    }
+
+    if cli.headless {
+        cli::run_headless(&config, app_signal_receiver, system_receiver)?;
+    } else {
+        run_gui(
+            app::LocalBackend::new(
+                from_frontend_sender,
+                audio_control_sender,
+                dmx_control_sender.clone(),
+                app_signal_receiver,
+                system_receiver,
+            ),
+            config,
+        )?;
+    }
+
+    // Shut down the DMX output cleanly (e.g. sACN stream termination).
+    dmx_control_sender.send(dmx::DMXControl::Shutdown)?;
+    supervisor.join(Worker::Dmx);
+
+    Ok(())
+}
 
+#[cfg(not(target_arch = "wasm32"))]
+fn run_gui(
+    backend: blaulicht::app::LocalBackend,
+    config: blaulicht::config::Config,
+) -> anyhow::Result<()> {
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -106,22 +184,18 @@ fn main() -> anyhow::Result<()> {
         Box::new(|cc| {
             Ok(Box::new(blaulicht::BlaulichtApp::new(
                 cc,
-                from_frontend_sender,
-                audio_thread_control_signal,
-                app_signal_receiver,
-                _system_receiver,
+                Box::new(backend),
                 config,
             )))
         }),
     )
-    .map_err(|err| anyhow!(err.to_string()))?;
-
-    Ok(())
+    .map_err(|err| anyhow::anyhow!(err.to_string()))
 }
 
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
 fn main() {
+    use blaulicht::{config::Config, remote::client::RemoteClient};
     use eframe::wasm_bindgen::JsCast as _;
 
     // Redirect `log` message to `console.log` and friends:
@@ -145,7 +219,15 @@ fn main() {
             .start(
                 canvas,
                 web_options,
-                Box::new(|cc| Ok(Box::new(eframe_template::TemplateApp::new(cc)))),
+                Box::new(|cc| {
+                    // The client connects to a running instance, see `remote::RemoteConfig`.
+                    let backend = RemoteClient::new(cc.egui_ctx.clone());
+                    Ok(Box::new(blaulicht::BlaulichtApp::new(
+                        cc,
+                        Box::new(backend),
+                        Config::default(),
+                    )))
+                }),
             )
             .await;
 
@@ -0,0 +1,384 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    str,
+};
+
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::FromFrontend,
+    audio::{
+        clock::{ClockControl, ClockSource},
+        AudioControl, SystemMessage,
+    },
+    effect::EffectControl,
+    events::Event,
+    supervisor::{Worker, WorkerError},
+    utils,
+};
+
+/// All addresses blaulicht listens to and sends start with this.
+const PREFIX: &str = "/blaulicht";
+const BUNDLE_TAG: &[u8; 8] = b"#bundle\0";
+/// Larger packets are cut off.
+const MAX_PACKET_SIZE: usize = 8192;
+
+/// The OSC server, controllers like TouchOSC, Resolume or QLab send their commands to it.
+///
+/// | address                   | arguments      | command                                |
+/// |---------------------------|----------------|----------------------------------------|
+/// | `/blaulicht/tap`          |                | tap tempo                              |
+/// | `/blaulicht/resync`       |                | makes now the downbeat                 |
+/// | `/blaulicht/nudge`        | number         | beat grid earlier (> 0) or later (< 0) |
+/// | `/blaulicht/bpm`          | number         | fixed tempo                            |
+/// | `/blaulicht/follow`       |                | the beat clock follows the audio       |
+/// | `/blaulicht/learn_room`   |                | calibrates the gain control            |
+/// | `/blaulicht/master`       | number `0..=1` | master dimmer                          |
+/// | `/blaulicht/scene`        | name           | switches to a scene                    |
+/// | `/blaulicht/scene/<name>` |                | switches to a scene                    |
+/// | `/blaulicht/effect/<id>`  | on/off         | enables an effect of the stack         |
+/// | `/blaulicht/audio/device` | name           | selects the audio input device         |
+///
+/// Buttons usually send 1 when pressed and 0 when released, commands without arguments are
+/// only triggered by a press (or a message without arguments).
+/// Feedback is sent by the event publisher to targets with the `osc` format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OscConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+}
+
+fn default_listen() -> SocketAddr {
+    ([0, 0, 0, 0], 9000).into()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArg {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(value) => Some(*value as f32),
+            OscArg::Float(value) => Some(*value),
+            OscArg::Bool(value) => Some(*value as u8 as f32),
+            OscArg::String(_) => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            OscArg::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Appends a string, NUL terminated and padded to a multiple of 4 bytes.
+fn write_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    packet.extend(std::iter::repeat(0).take(padding));
+}
+
+fn read_string<'a>(packet: &mut &'a [u8]) -> Result<&'a str> {
+    let Some(end) = packet.iter().position(|b| *b == 0) else {
+        bail!("OSC string is not terminated");
+    };
+    let value = str::from_utf8(&packet[..end]).context("OSC string is not UTF-8")?;
+    let padded = (end / 4 + 1) * 4;
+    *packet = packet.get(padded..).unwrap_or_default();
+    Ok(value)
+}
+
+fn read_4(packet: &mut &[u8]) -> Result<[u8; 4]> {
+    let Some(bytes) = packet.get(..4) else {
+        bail!("OSC packet is truncated");
+    };
+    let bytes = bytes.try_into()?;
+    *packet = &packet[4..];
+    Ok(bytes)
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = vec![];
+        write_string(&mut packet, &self.address);
+
+        let tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+                OscArg::Bool(true) => 'T',
+                OscArg::Bool(false) => 'F',
+            }))
+            .collect();
+        write_string(&mut packet, &tags);
+
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => packet.extend(value.to_be_bytes()),
+                OscArg::Float(value) => packet.extend(value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut packet, value),
+                OscArg::Bool(_) => {}
+            }
+        }
+
+        packet
+    }
+
+    /// Decodes a packet, the messages of bundles are returned in order (time tags are ignored).
+    pub fn decode(packet: &[u8]) -> Result<Vec<Self>> {
+        let mut messages = vec![];
+        Self::decode_into(packet, &mut messages)?;
+        Ok(messages)
+    }
+
+    fn decode_into(mut packet: &[u8], messages: &mut Vec<Self>) -> Result<()> {
+        if let Some(mut elements) = packet.strip_prefix(BUNDLE_TAG) {
+            // Time tag.
+            elements = elements.get(8..).context("OSC bundle is truncated")?;
+            while !elements.is_empty() {
+                let size = i32::from_be_bytes(read_4(&mut elements)?) as usize;
+                let element = elements.get(..size).context("OSC bundle is truncated")?;
+                Self::decode_into(element, messages)?;
+                elements = &elements[size..];
+            }
+            return Ok(());
+        }
+
+        let address = read_string(&mut packet)?.to_string();
+        if !address.starts_with('/') {
+            bail!("`{address}` is not an OSC address");
+        }
+
+        // Very old clients omit the type tags.
+        let tags = if packet.is_empty() {
+            ","
+        } else {
+            read_string(&mut packet)?
+        };
+        let Some(tags) = tags.strip_prefix(',') else {
+            bail!("OSC message `{address}` has no type tags");
+        };
+
+        let mut args = vec![];
+        for tag in tags.chars() {
+            let arg = match tag {
+                'i' => OscArg::Int(i32::from_be_bytes(read_4(&mut packet)?)),
+                'f' => OscArg::Float(f32::from_be_bytes(read_4(&mut packet)?)),
+                's' => OscArg::String(read_string(&mut packet)?.to_string()),
+                'T' => OscArg::Bool(true),
+                'F' => OscArg::Bool(false),
+                _ => bail!("OSC message `{address}`: type `{tag}` is not supported"),
+            };
+            args.push(arg);
+        }
+
+        messages.push(Self { address, args });
+        Ok(())
+    }
+}
+
+/// The feedback message of an event.
+pub fn event_message(event: &Event) -> OscMessage {
+    use OscArg::{Float, Int, String};
+
+    match *event {
+        Event::Beat { beat } => OscMessage::new(format!("{PREFIX}/beat"), vec![Int(beat as i32)]),
+        Event::Bar { bar } => OscMessage::new(format!("{PREFIX}/bar"), vec![Int(bar as i32)]),
+        Event::Tempo { bpm, confidence } => OscMessage::new(
+            format!("{PREFIX}/tempo"),
+            vec![Float(bpm), Float(confidence)],
+        ),
+        Event::Volume { level } => OscMessage::new(format!("{PREFIX}/volume"), vec![Float(level)]),
+        Event::Band {
+            id,
+            energy,
+            peak,
+            onset,
+        } => OscMessage::new(
+            format!("{PREFIX}/band/{id}"),
+            vec![Float(energy), Float(peak), Int(onset as i32)],
+        ),
+        Event::Drop { active } => {
+            OscMessage::new(format!("{PREFIX}/drop"), vec![Int(active as i32)])
+        }
+        Event::Section {
+            from,
+            to,
+            confidence,
+        } => OscMessage::new(
+            format!("{PREFIX}/section"),
+            vec![
+                String(to.to_string()),
+                String(from.to_string()),
+                Float(confidence),
+            ],
+        ),
+    }
+}
+
+/// What an OSC message asks for.
+enum Command {
+    Frontend(FromFrontend),
+    Audio(AudioControl),
+}
+
+/// True for a button press or a message without arguments.
+fn pressed(args: &[OscArg]) -> bool {
+    args.first()
+        .map_or(true, |arg| arg.as_f32().map_or(true, |value| value > 0.0))
+}
+
+fn number(message: &OscMessage) -> Result<f32> {
+    message
+        .args
+        .first()
+        .and_then(OscArg::as_f32)
+        .filter(|number| number.is_finite())
+        .with_context(|| format!("`{}` needs a number", message.address))
+}
+
+fn string(message: &OscMessage) -> Result<&str> {
+    message
+        .args
+        .first()
+        .and_then(OscArg::as_str)
+        .with_context(|| format!("`{}` needs a string", message.address))
+}
+
+fn command(message: &OscMessage) -> Result<Option<Command>> {
+    let Some(path) = message.address.strip_prefix(PREFIX) else {
+        bail!("Unknown OSC address `{}`", message.address);
+    };
+    let clock = |control| Ok(Some(Command::Audio(AudioControl::Clock(control))));
+    let effects = |control| Ok(Some(Command::Audio(AudioControl::Effects(control))));
+    let pressed = pressed(&message.args);
+
+    match path {
+        "/tap" | "/resync" | "/follow" | "/learn_room" if !pressed => Ok(None),
+        "/tap" => clock(ClockControl::Tap),
+        "/resync" => clock(ClockControl::Resync),
+        "/follow" => clock(ClockControl::Source(ClockSource::Tracker)),
+        "/learn_room" => Ok(Some(Command::Audio(AudioControl::LearnRoom))),
+        "/nudge" => match number(message)? {
+            value if value > 0.0 => clock(ClockControl::NudgeForward),
+            value if value < 0.0 => clock(ClockControl::NudgeBack),
+            _ => Ok(None),
+        },
+        "/bpm" => clock(ClockControl::Manual(number(message)?)),
+        "/master" => effects(EffectControl::Master(number(message)?)),
+        "/scene" => effects(EffectControl::Scene(string(message)?.to_string())),
+        "/audio/device" => {
+            let name = string(message)?;
+            let Some(device) = utils::device_from_name(name.to_string()) else {
+                bail!("There is no audio device `{name}`");
+            };
+            Ok(Some(Command::Frontend(FromFrontend::SelectInputDevice(
+                Some(device),
+            ))))
+        }
+        _ => {
+            if let Some(name) = path.strip_prefix("/scene/") {
+                return if pressed {
+                    effects(EffectControl::Scene(name.to_string()))
+                } else {
+                    Ok(None)
+                };
+            }
+
+            if let Some(id) = path.strip_prefix("/effect/") {
+                let id = id
+                    .parse()
+                    .with_context(|| format!("`{id}` is not an effect id"))?;
+                return effects(EffectControl::Enable(id, pressed));
+            }
+
+            bail!("Unknown OSC address `{}`", message.address)
+        }
+    }
+}
+
+/// OSC server thread: receives commands and forwards them like the GUI does.
+pub fn osc_thread(
+    config: OscConfig,
+    from_frontend: Sender<FromFrontend>,
+    audio_control: Sender<AudioControl>,
+    system_out: Sender<SystemMessage>,
+) {
+    let socket = match UdpSocket::bind(config.listen) {
+        Ok(socket) => socket,
+        Err(err) => {
+            warn!("[OSC] Could not listen on {}: {err}", config.listen);
+            system_out
+                .send(SystemMessage::Error(WorkerError::new(
+                    Worker::Osc,
+                    format!("Could not listen on {}: {err}", config.listen),
+                )))
+                .ok();
+            return;
+        }
+    };
+    info!("[OSC] Listening on {}", config.listen);
+
+    let mut buf = [0; MAX_PACKET_SIZE];
+    loop {
+        let (len, source) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) => {
+                warn!("[OSC] Receiving failed: {err}");
+                continue;
+            }
+        };
+
+        let messages = match OscMessage::decode(&buf[..len]) {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("[OSC] Invalid packet from {source}: {err}");
+                continue;
+            }
+        };
+
+        for message in messages {
+            let sent = match command(&message) {
+                Ok(Some(Command::Frontend(command))) => from_frontend.send(command).is_ok(),
+                Ok(Some(Command::Audio(control))) => audio_control.send(control).is_ok(),
+                Ok(None) => true,
+                Err(err) => {
+                    warn!("[OSC] {err}");
+                    true
+                }
+            };
+
+            if !sent {
+                info!("[OSC] Shutting down, the receivers are gone");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
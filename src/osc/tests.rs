@@ -0,0 +1,83 @@
+use super::{command, number, OscArg, OscMessage, BUNDLE_TAG};
+
+fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+    OscMessage::new(address, args)
+}
+
+#[test]
+fn messages_survive_a_round_trip() {
+    let sent = message(
+        "/blaulicht/test",
+        vec![
+            OscArg::Int(-7),
+            OscArg::Float(0.25),
+            OscArg::String("drop".into()),
+            OscArg::Bool(true),
+            OscArg::Bool(false),
+        ],
+    );
+
+    assert_eq!(OscMessage::decode(&sent.encode()).unwrap(), vec![sent]);
+}
+
+#[test]
+fn strings_are_terminated_and_padded_to_4_bytes() {
+    // Exactly 4 characters still need a terminating NUL, which takes another 4 bytes.
+    let packet = message("/abc", vec![]).encode();
+    assert_eq!(packet, b"/abc\0\0\0\0,\0\0\0");
+
+    let packet = message("/ab", vec![OscArg::Int(1)]).encode();
+    assert_eq!(packet, b"/ab\0,i\0\0\0\0\0\x01");
+}
+
+#[test]
+fn bundles_are_flattened_in_order() {
+    let first = message("/blaulicht/tap", vec![]);
+    let second = message("/blaulicht/bpm", vec![OscArg::Float(128.0)]);
+
+    let mut packet = BUNDLE_TAG.to_vec();
+    packet.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+    for element in [&first, &second] {
+        let element = element.encode();
+        packet.extend((element.len() as i32).to_be_bytes());
+        packet.extend(element);
+    }
+
+    assert_eq!(OscMessage::decode(&packet).unwrap(), vec![first, second]);
+}
+
+#[test]
+fn messages_without_type_tags_have_no_arguments() {
+    let decoded = OscMessage::decode(b"/blaulicht/tap\0\0").unwrap();
+    assert_eq!(decoded, vec![message("/blaulicht/tap", vec![])]);
+}
+
+#[test]
+fn truncated_packets_are_rejected() {
+    let packet = message("/blaulicht/bpm", vec![OscArg::Float(128.0)]).encode();
+    for end in [0, 5, packet.len() - 1] {
+        assert!(OscMessage::decode(&packet[..end]).is_err(), "{end} bytes");
+    }
+
+    // The size of the element is larger than the bundle.
+    let mut bundle = BUNDLE_TAG.to_vec();
+    bundle.extend([0; 8]);
+    bundle.extend(16i32.to_be_bytes());
+    bundle.extend(b"/a\0\0,\0\0\0");
+    assert!(OscMessage::decode(&bundle).is_err());
+
+    // No time tag.
+    assert!(OscMessage::decode(BUNDLE_TAG).is_err());
+}
+
+#[test]
+fn numbers_have_to_be_finite() {
+    for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        let bpm = message("/blaulicht/bpm", vec![OscArg::Float(value)]);
+        assert!(number(&bpm).is_err(), "{value}");
+        assert!(command(&bpm).is_err(), "{value}");
+    }
+
+    let master = message("/blaulicht/master", vec![OscArg::Int(1)]);
+    assert_eq!(number(&master).unwrap(), 1.0);
+}
@@ -0,0 +1,216 @@
+use std::net::SocketAddr;
+
+use anyhow::{bail, Result};
+use cpal::traits::DeviceTrait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{Signal, SystemMessage},
+    config::Config,
+    dmx::{
+        artnet::ArtNetNode,
+        universe::{OutputConfig, UniverseId},
+        OutputState,
+    },
+    supervisor::{Worker, WorkerError, WorkerState},
+};
+
+#[cfg(target_arch = "wasm32")]
+pub mod client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+
+/// The HTTP server for remote controls, e.g. phones at the venue or the web build.
+/// Bodies and responses are JSON, errors are `{"error": "..."}`.
+///
+/// | method | path                 | body                      | response                          |
+/// |--------|----------------------|---------------------------|-----------------------------------|
+/// | GET    | `/api/devices`       |                           | audio inputs and serial ports     |
+/// | POST   | `/api/devices/audio` | `{"name": "..."}` or `{}` | selects the audio input (or none) |
+/// | GET    | `/api/patch`         |                           | universes and patched fixtures    |
+/// | POST   | `/api/output`        | see [`SelectOutput`]      | switches the output of a universe |
+/// | GET    | `/api/scenes`        |                           | names of the scenes               |
+/// | POST   | `/api/scene`         | `{"name": "..."}`         | switches to a scene               |
+/// | POST   | `/api/master`        | `{"level": 0.8}`          | master dimmer, `0..=1`            |
+/// | POST   | `/api/blackout`      | `{"active": true}`        | blackout on or off                |
+/// | POST   | `/api/control`       | e.g. `{"clock": "tap"}`   | any command of the GUI            |
+/// | GET    | `/api/stream`        |                           | WebSocket, see [`Update`]         |
+///
+/// Commands are answered with 204 once they are queued, their effect shows up in the stream.
+///
+/// Web pages other than `allowed_origins` are rejected, so that any page opened on the network
+/// cannot control the show. With a `token` every request has to send it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// Pages which may use the API, e.g. `http://192.168.1.20:8000` serving the web build.
+    /// Clients which are not browsers send no origin and are not affected.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Sent as `Authorization: Bearer <token>`, or as `?token=<token>` since browsers cannot set
+    /// headers on a WebSocket.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl RemoteConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(token) = &self.token {
+            // Passed in URLs as is.
+            let url_safe = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+            if token.is_empty() || !token.chars().all(url_safe) {
+                bail!("`remote.token` must consist of letters, digits, `-` and `_`");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn default_listen() -> SocketAddr {
+    ([0, 0, 0, 0], 8080).into()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioDevice {
+    pub host: String,
+    pub name: String,
+}
+
+impl AudioDevice {
+    pub fn new(host: cpal::HostId, device: &cpal::Device) -> Self {
+        Self {
+            host: host.name().to_string(),
+            name: device.name().unwrap_or_default(),
+        }
+    }
+}
+
+/// A system message as it is streamed, devices are reduced to their names.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SystemUpdate {
+    Log {
+        message: String,
+    },
+    Error(WorkerError),
+    Worker {
+        worker: Worker,
+        state: WorkerState,
+    },
+    AudioSelected {
+        device: Option<String>,
+    },
+    AudioDevices {
+        devices: Vec<AudioDevice>,
+    },
+    OutputSelected {
+        universe: UniverseId,
+        output: OutputConfig,
+    },
+    OutputState {
+        universe: UniverseId,
+        state: OutputState,
+    },
+    SerialDevices {
+        ports: Vec<String>,
+    },
+    ArtNetNodes {
+        nodes: Vec<ArtNetNode>,
+    },
+    AgcCalibrated {
+        gain: f32,
+    },
+}
+
+/// A message of the WebSocket stream, e.g. `{"signal":{"type":"beat","value":0}}` or
+/// `{"system":{"type":"log","message":"..."}}`.
+/// The stream starts with the config of the instance.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Update {
+    Config(Box<Config>),
+    Signal(Signal),
+    System(SystemUpdate),
+}
+
+/// Messages for the GUI which are streamed to the WebSocket clients as well.
+pub trait Streamed: Send + 'static {
+    fn update(&self) -> Option<Update>;
+}
+
+impl Streamed for Signal {
+    fn update(&self) -> Option<Update> {
+        Some(Update::Signal(*self))
+    }
+}
+
+impl Streamed for SystemMessage {
+    fn update(&self) -> Option<Update> {
+        let update = match self {
+            SystemMessage::Log(message) => SystemUpdate::Log {
+                message: message.clone(),
+            },
+            SystemMessage::Error(error) => SystemUpdate::Error(error.clone()),
+            SystemMessage::Worker(worker, state) => SystemUpdate::Worker {
+                worker: *worker,
+                state: state.clone(),
+            },
+            SystemMessage::LoopSpeed(_) => return None,
+            SystemMessage::AudioSelected(device) => SystemUpdate::AudioSelected {
+                device: device.as_ref().and_then(|device| device.name().ok()),
+            },
+            SystemMessage::AudioDevicesView(devices) => SystemUpdate::AudioDevices {
+                devices: devices
+                    .iter()
+                    .map(|(host, device)| AudioDevice::new(*host, device))
+                    .collect(),
+            },
+            SystemMessage::OutputSelected(universe, output) => SystemUpdate::OutputSelected {
+                universe: *universe,
+                output: output.clone(),
+            },
+            SystemMessage::OutputState(universe, state) => SystemUpdate::OutputState {
+                universe: *universe,
+                state: *state,
+            },
+            SystemMessage::SerialDevicesView(ports) => SystemUpdate::SerialDevices {
+                ports: ports.iter().map(|port| port.port_name.clone()).collect(),
+            },
+            SystemMessage::ArtNetNodesView(nodes) => SystemUpdate::ArtNetNodes {
+                nodes: nodes.clone(),
+            },
+            SystemMessage::AgcCalibrated(gain) => SystemUpdate::AgcCalibrated { gain: *gain },
+        };
+        Some(Update::System(update))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SelectAudio {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SelectScene {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetMaster {
+    pub level: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetBlackout {
+    pub active: bool,
+}
+
+/// E.g. `{"universe": 1, "output": {"type": "serial", "port": "/dev/ttyUSB0"}}`.
+#[derive(Serialize, Deserialize)]
+pub struct SelectOutput {
+    pub universe: UniverseId,
+    pub output: OutputConfig,
+}
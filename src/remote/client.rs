@@ -0,0 +1,259 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use eframe::wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    CloseEvent, Headers, MessageEvent, RequestInit, Response, UrlSearchParams, WebSocket,
+};
+
+use super::{AudioDevice, SelectAudio, SelectOutput, SystemUpdate, Update};
+use crate::{
+    app::Backend,
+    audio::AudioControl,
+    dmx::universe::{OutputConfig, UniverseId},
+};
+
+/// Updates which arrive while the tab is hidden are not drawn, only the latest ones are kept.
+const MAX_PENDING_UPDATES: usize = 4096;
+
+type Inbox = Arc<Mutex<VecDeque<Update>>>;
+
+fn push(inbox: &Inbox, ctx: &egui::Context, update: Update) {
+    let mut inbox = inbox.lock().unwrap();
+    if inbox.len() == MAX_PENDING_UPDATES {
+        inbox.pop_front();
+    }
+    inbox.push_back(update);
+    ctx.request_repaint();
+}
+
+fn log(message: String) -> Update {
+    Update::System(SystemUpdate::Log { message })
+}
+
+/// A parameter of the page's query, e.g. `?server=host:port&token=...`.
+fn page_param(name: &str) -> Option<String> {
+    let query = web_sys::window()?.location().search().ok()?;
+    UrlSearchParams::new_with_str(&query).ok()?.get(name)
+}
+
+/// The server given as `?server=host:port`, by default port 8080 of the host serving the page.
+fn default_server() -> String {
+    if let Some(server) = page_param("server") {
+        return server;
+    }
+
+    let host = web_sys::window()
+        .and_then(|window| window.location().hostname().ok())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    format!("{host}:8080")
+}
+
+/// Browsers block insecure requests from a page served over https, e.g. from GitHub Pages, the
+/// server is then reached over TLS as well.
+fn secure_page() -> bool {
+    web_sys::window()
+        .and_then(|window| window.location().protocol().ok())
+        .is_some_and(|protocol| protocol == "https:")
+}
+
+/// POSTs JSON, the error of a rejected request is taken from its `{"error": "..."}` body.
+async fn post_json(url: String, body: String, token: Option<String>) -> Result<(), String> {
+    #[derive(Deserialize)]
+    struct Error {
+        error: String,
+    }
+
+    let window = web_sys::window().ok_or("There is no window")?;
+    let init = RequestInit::new();
+    init.set_method("POST");
+    init.set_body(&JsValue::from_str(&body));
+    if let Some(token) = token {
+        let headers = Headers::new().map_err(|err| format!("{err:?}"))?;
+        headers
+            .set("Authorization", &format!("Bearer {token}"))
+            .map_err(|err| format!("{err:?}"))?;
+        init.set_headers(&headers);
+    }
+
+    let response = JsFuture::from(window.fetch_with_str_and_init(&url, &init))
+        .await
+        .map_err(|err| format!("{err:?}"))?;
+    let response: Response = response.dyn_into().map_err(|err| format!("{err:?}"))?;
+    if response.ok() {
+        return Ok(());
+    }
+
+    let text = match response.text() {
+        Ok(text) => JsFuture::from(text)
+            .await
+            .ok()
+            .and_then(|text| text.as_string()),
+        Err(_) => None,
+    };
+    Err(text
+        .and_then(|text| serde_json::from_str::<Error>(&text).ok())
+        .map_or_else(|| response.status_text(), |body| body.error))
+}
+
+/// A WebSocket to `/api/stream` and its callbacks, which must live as long as the socket.
+struct Connection {
+    server: String,
+    socket: WebSocket,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+impl Connection {
+    fn open(server: &str, token: Option<&str>, inbox: &Inbox, ctx: &egui::Context) -> Result<Self> {
+        // Browsers cannot set the `Authorization` header of a WebSocket.
+        let query = token.map_or_else(String::new, |token| format!("?token={token}"));
+        let scheme = if secure_page() { "wss" } else { "ws" };
+        let socket = WebSocket::new(&format!("{scheme}://{server}/api/stream{query}"))
+            .map_err(|err| anyhow!("{err:?}"))?;
+
+        let on_message = {
+            let inbox = inbox.clone();
+            let ctx = ctx.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let Some(text) = event.data().as_string() else {
+                    return;
+                };
+                let update = serde_json::from_str(&text)
+                    .unwrap_or_else(|err| log(format!("Invalid update from the server: {err}")));
+                push(&inbox, &ctx, update);
+            })
+        };
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let on_close = {
+            let inbox = inbox.clone();
+            let ctx = ctx.clone();
+            let server = server.to_string();
+            Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+                let message = format!("Connection to {server} closed (code {})", event.code());
+                push(&inbox, &ctx, log(message));
+            })
+        };
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            server: server.to_string(),
+            socket,
+            _on_message: on_message,
+            _on_close: on_close,
+        })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        self.socket.close().ok();
+    }
+}
+
+/// The backend of the web build: it streams the updates of a running instance over a
+/// WebSocket and sends the commands to its HTTP API, see [`super::RemoteConfig`].
+pub struct RemoteClient {
+    ctx: egui::Context,
+    /// The address in the menu bar, used once `Connect` is clicked.
+    server: String,
+    /// Given as `?token=...`, see [`super::RemoteConfig::token`].
+    token: Option<String>,
+    connection: Option<Connection>,
+    inbox: Inbox,
+}
+
+impl RemoteClient {
+    pub fn new(ctx: egui::Context) -> Self {
+        let mut client = Self {
+            ctx,
+            server: default_server(),
+            token: page_param("token"),
+            connection: None,
+            inbox: Inbox::default(),
+        };
+        client.connect();
+        client
+    }
+
+    fn connect(&mut self) {
+        self.connection = None;
+        self.inbox.lock().unwrap().clear();
+
+        match Connection::open(&self.server, self.token.as_deref(), &self.inbox, &self.ctx) {
+            Ok(connection) => self.connection = Some(connection),
+            Err(err) => push(
+                &self.inbox,
+                &self.ctx,
+                log(format!("Could not connect to {}: {err:#}", self.server)),
+            ),
+        }
+    }
+
+    /// Errors are answered asynchronously, they end up in the log.
+    fn post(&self, path: &str, body: &impl Serialize) -> Result<()> {
+        let connection = self
+            .connection
+            .as_ref()
+            .context("Not connected to a server")?;
+        let scheme = if secure_page() { "https" } else { "http" };
+        let url = format!("{scheme}://{}{path}", connection.server);
+        let body = serde_json::to_string(body)?;
+
+        let path = path.to_string();
+        let token = self.token.clone();
+        let inbox = self.inbox.clone();
+        let ctx = self.ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = post_json(url, body, token).await {
+                push(&inbox, &ctx, log(format!("`{path}` failed: {err}")));
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl Backend for RemoteClient {
+    fn try_recv(&mut self) -> Option<Update> {
+        self.inbox.lock().unwrap().pop_front()
+    }
+
+    fn control(&mut self, control: AudioControl) -> Result<()> {
+        self.post("/api/control", &control)
+    }
+
+    fn select_audio_device(&mut self, device: Option<AudioDevice>) -> Result<()> {
+        let name = device.map(|device| device.name);
+        self.post("/api/devices/audio", &SelectAudio { name })
+    }
+
+    fn select_output(&mut self, universe: UniverseId, output: OutputConfig) -> Result<()> {
+        self.post("/api/output", &SelectOutput { universe, output })
+    }
+
+    fn status_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Server:");
+        let response = ui.add(egui::TextEdit::singleline(&mut self.server).desired_width(160.0));
+        let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Connect").clicked() || submitted {
+            self.connect();
+        }
+
+        let state = match self.connection.as_ref().map(|c| c.socket.ready_state()) {
+            Some(WebSocket::CONNECTING) => "connecting",
+            Some(WebSocket::OPEN) => "connected",
+            _ => "disconnected",
+        };
+        ui.label(state);
+    }
+}
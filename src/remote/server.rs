@@ -0,0 +1,442 @@
+use std::{
+    io::{Cursor, Read},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server, StatusCode};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use super::{
+    AudioDevice, RemoteConfig, SelectAudio, SelectOutput, SelectScene, SetBlackout, SetMaster,
+    Streamed, Update,
+};
+use crate::{
+    app::FromFrontend,
+    audio::{AudioControl, SystemMessage},
+    config::Config,
+    dmx::{universe::UniverseConfig, DMXControl},
+    effect::EffectControl,
+    fixture::patch::FixtureConfig,
+    supervisor::{Worker, WorkerError},
+    utils,
+};
+
+/// Request bodies are tiny, larger ones are rejected.
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+/// Updates a WebSocket client may fall behind before it is dropped.
+const STREAM_BUFFER: usize = 1024;
+/// Idle streams are pinged this often, which also notices clients which are gone.
+const KEEPALIVE: Duration = Duration::from_secs(5);
+
+/// The connected WebSocket clients, each one gets its own channel of JSON messages.
+#[derive(Clone, Default)]
+pub struct Subscribers(Arc<Mutex<Vec<Sender<String>>>>);
+
+impl Subscribers {
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = crossbeam_channel::bounded(STREAM_BUFFER);
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn publish(&self, update: &Update) {
+        let mut subscribers = self.0.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let text = serde_json::to_string(update).expect("updates always serialize");
+        // Clients which are gone or too slow are dropped.
+        subscribers.retain(|subscriber| subscriber.try_send(text.clone()).is_ok());
+    }
+}
+
+/// Returns a sender which passes the messages on to `out` and streams them to the clients.
+pub fn forward<T: Streamed>(out: Sender<T>, subscribers: Subscribers) -> Sender<T> {
+    let (sender, receiver) = crossbeam_channel::unbounded::<T>();
+    thread::spawn(move || {
+        for message in receiver {
+            if let Some(update) = message.update() {
+                subscribers.publish(&update);
+            }
+            // The GUI might be closed already, the stream keeps going.
+            out.send(message).ok();
+        }
+    });
+    sender
+}
+
+#[derive(Serialize)]
+struct Devices {
+    audio: Vec<AudioDevice>,
+    serial: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PatchView<'a> {
+    universes: &'a [UniverseConfig],
+    fixtures: &'a [FixtureConfig],
+}
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("headers are valid")
+}
+
+fn header_value<'a>(request: &'a Request, field: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(field))
+        .map(|h| h.value.as_str())
+}
+
+/// The URL without its query.
+fn path(request: &Request) -> &str {
+    request.url().split('?').next().unwrap_or_default()
+}
+
+fn query_param<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    let (_, query) = request.url().split_once('?')?;
+    query.split('&').find_map(|pair| {
+        pair.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+fn json(status: u16, value: &impl Serialize) -> HttpResponse {
+    Response::from_data(serde_json::to_vec(value).expect("responses always serialize"))
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error(status: u16, message: impl Into<String>) -> HttpResponse {
+    #[derive(Serialize)]
+    struct Error {
+        error: String,
+    }
+
+    json(
+        status,
+        &Error {
+            error: message.into(),
+        },
+    )
+}
+
+fn respond(request: Request, response: HttpResponse) {
+    if let Err(err) = request.respond(response) {
+        warn!("[remote] Could not respond: {err}");
+    }
+}
+
+fn no_content() -> HttpResponse {
+    Response::from_data(vec![]).with_status_code(204)
+}
+
+fn read_json<T: DeserializeOwned>(request: &mut Request) -> Result<T> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_string(&mut body)
+        .context("Could not read the request body")?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        bail!("The request body is too large");
+    }
+
+    serde_json::from_str(&body).context("Invalid request body")
+}
+
+/// The HTTP API, owns nothing but the senders the GUI uses as well.
+struct Remote {
+    remote_config: RemoteConfig,
+    /// The config the threads were started with, selections made since then are only in the
+    /// file, see [`Config::reread`].
+    config: Config,
+    from_frontend: Sender<FromFrontend>,
+    audio_control: Sender<AudioControl>,
+    dmx_control: Sender<DMXControl>,
+    subscribers: Subscribers,
+}
+
+impl Remote {
+    fn send<T>(sender: &Sender<T>, message: T) -> HttpResponse {
+        match sender.send(message) {
+            Ok(()) => no_content(),
+            Err(_) => error(503, "The audio thread is not running"),
+        }
+    }
+
+    fn effects(&self, control: EffectControl) -> HttpResponse {
+        Self::send(&self.audio_control, AudioControl::Effects(control))
+    }
+
+    /// The origin of a request from a web page, which is rejected unless it is allowed.
+    /// Other clients send no origin.
+    fn check_origin(&self, request: &Request) -> Result<Option<String>, HttpResponse> {
+        let Some(origin) = header_value(request, "Origin") else {
+            return Ok(None);
+        };
+
+        if self
+            .remote_config
+            .allowed_origins
+            .iter()
+            .any(|o| o == origin)
+        {
+            Ok(Some(origin.to_string()))
+        } else {
+            Err(error(
+                403,
+                format!("`{origin}` is not in `remote.allowed_origins`"),
+            ))
+        }
+    }
+
+    fn check_token(&self, request: &Request) -> Result<(), HttpResponse> {
+        let Some(token) = &self.remote_config.token else {
+            return Ok(());
+        };
+        // Browsers cannot add the token to a CORS preflight.
+        if *request.method() == Method::Options {
+            return Ok(());
+        }
+
+        let sent = header_value(request, "Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| query_param(request, "token"));
+        if sent == Some(token.as_str()) {
+            Ok(())
+        } else {
+            Err(error(401, "The token is missing or wrong"))
+        }
+    }
+
+    fn handle(&self, request: &mut Request) -> Result<HttpResponse> {
+        let path = path(request).to_string();
+
+        let response = match (request.method(), path.as_str()) {
+            // CORS preflight, browsers send it before posting JSON.
+            (Method::Options, _) => no_content()
+                .with_header(header("Access-Control-Allow-Methods", "GET, POST"))
+                .with_header(header(
+                    "Access-Control-Allow-Headers",
+                    "Content-Type, Authorization",
+                )),
+            (Method::Get, "/api/devices") => json(
+                200,
+                &Devices {
+                    audio: utils::get_input_devices_flat()
+                        .iter()
+                        .map(|(host, device)| AudioDevice::new(*host, device))
+                        .collect(),
+                    serial: serialport::available_ports()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|port| port.port_name)
+                        .collect(),
+                },
+            ),
+            (Method::Post, "/api/devices/audio") => {
+                let SelectAudio { name } = read_json(request)?;
+                let device = match name {
+                    Some(name) => match utils::device_from_name(name.clone()) {
+                        Some(device) => Some(device),
+                        None => {
+                            return Ok(error(404, format!("There is no audio device `{name}`")))
+                        }
+                    },
+                    None => None,
+                };
+                Self::send(&self.from_frontend, FromFrontend::SelectInputDevice(device))
+            }
+            (Method::Get, "/api/patch") => {
+                let config = self.config.reread();
+                json(
+                    200,
+                    &PatchView {
+                        universes: &config.universes,
+                        fixtures: &config.patch,
+                    },
+                )
+            }
+            (Method::Post, "/api/output") => {
+                let SelectOutput { universe, output } = read_json(request)?;
+                if !self.config.universes.iter().any(|u| u.id == universe) {
+                    return Ok(error(404, format!("There is no universe {universe}")));
+                }
+                match self
+                    .dmx_control
+                    .send(DMXControl::ChangePort(universe, output))
+                {
+                    Ok(()) => no_content(),
+                    Err(_) => error(503, "The DMX thread is not running"),
+                }
+            }
+            (Method::Get, "/api/scenes") => json(
+                200,
+                &self
+                    .config
+                    .scenes
+                    .iter()
+                    .map(|scene| &scene.name)
+                    .collect::<Vec<_>>(),
+            ),
+            (Method::Post, "/api/scene") => {
+                let SelectScene { name } = read_json(request)?;
+                if !self.config.scenes.iter().any(|scene| scene.name == name) {
+                    return Ok(error(404, format!("There is no scene `{name}`")));
+                }
+                self.effects(EffectControl::Scene(name))
+            }
+            (Method::Post, "/api/master") => {
+                let SetMaster { level } = read_json(request)?;
+                if !(0.0..=1.0).contains(&level) {
+                    bail!("The master level must be between 0 and 1");
+                }
+                self.effects(EffectControl::Master(level))
+            }
+            (Method::Post, "/api/blackout") => {
+                let SetBlackout { active } = read_json(request)?;
+                self.effects(EffectControl::Blackout(active))
+            }
+            (Method::Post, "/api/control") => {
+                let control: AudioControl = read_json(request)?;
+                Self::send(&self.audio_control, control)
+            }
+            (
+                _,
+                "/api/devices" | "/api/devices/audio" | "/api/patch" | "/api/output"
+                | "/api/scenes" | "/api/scene" | "/api/master" | "/api/blackout" | "/api/control"
+                | "/api/stream",
+            ) => error(405, "Method not allowed"),
+            _ => error(404, format!("Unknown path `{path}`")),
+        };
+
+        Ok(response)
+    }
+
+    /// Answers a request, or upgrades it to a stream.
+    fn serve(&self, mut request: Request) {
+        let origin = match self.check_origin(&request) {
+            Ok(origin) => origin,
+            Err(response) => return respond(request, response),
+        };
+
+        let response = match self.check_token(&request) {
+            Ok(()) if path(&request) == "/api/stream" && *request.method() == Method::Get => {
+                return self.stream(request);
+            }
+            Ok(()) => self
+                .handle(&mut request)
+                .unwrap_or_else(|err| error(400, format!("{err:#}"))),
+            Err(response) => response,
+        };
+
+        let response = match origin {
+            Some(origin) => response
+                .with_header(header("Access-Control-Allow-Origin", &origin))
+                .with_header(header("Vary", "Origin")),
+            None => response,
+        };
+        respond(request, response);
+    }
+
+    /// Upgrades the request to a WebSocket which streams the updates.
+    fn stream(&self, request: Request) {
+        let Some(key) = header_value(&request, "Sec-WebSocket-Key")
+            .map(|key| derive_accept_key(key.as_bytes()))
+        else {
+            request
+                .respond(error(400, "Expected a WebSocket handshake"))
+                .ok();
+            return;
+        };
+
+        let response = Response::empty(StatusCode(101))
+            .with_header(header("Upgrade", "websocket"))
+            .with_header(header("Connection", "Upgrade"))
+            .with_header(header("Sec-WebSocket-Accept", &key));
+        let stream = request.upgrade("websocket", response);
+        let config = Update::Config(Box::new(self.config.reread()));
+        let config = serde_json::to_string(&config).expect("updates always serialize");
+        let updates = self.subscribers.subscribe();
+        thread::spawn(move || stream_updates(stream, config, updates));
+    }
+}
+
+fn stream_updates(stream: Box<dyn ReadWrite + Send>, config: String, updates: Receiver<String>) {
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    if socket.send(Message::Text(config)).is_err() {
+        return;
+    }
+
+    loop {
+        let message = match updates.recv_timeout(KEEPALIVE) {
+            Ok(text) => Message::Text(text),
+            Err(RecvTimeoutError::Timeout) => Message::Ping(vec![]),
+            // Dropped for falling behind.
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if socket.send(message).is_err() {
+            return;
+        }
+    }
+
+    socket.close(None).ok();
+    socket.flush().ok();
+}
+
+/// Remote API thread: answers HTTP requests and hands out the update streams.
+pub fn remote_thread(
+    remote_config: RemoteConfig,
+    config: Config,
+    from_frontend: Sender<FromFrontend>,
+    audio_control: Sender<AudioControl>,
+    dmx_control: Sender<DMXControl>,
+    subscribers: Subscribers,
+    system_out: Sender<SystemMessage>,
+) {
+    let server = match Server::http(remote_config.listen) {
+        Ok(server) => server,
+        Err(err) => {
+            warn!(
+                "[remote] Could not listen on {}: {err}",
+                remote_config.listen
+            );
+            system_out
+                .send(SystemMessage::Error(WorkerError::new(
+                    Worker::Remote,
+                    format!("Could not listen on {}: {err}", remote_config.listen),
+                )))
+                .ok();
+            return;
+        }
+    };
+    info!("[remote] Listening on http://{}", remote_config.listen);
+
+    let remote = Remote {
+        remote_config,
+        config,
+        from_frontend,
+        audio_control,
+        dmx_control,
+        subscribers,
+    };
+
+    for request in server.incoming_requests() {
+        remote.serve(request);
+    }
+}
+
+#[cfg(test)]
+mod tests;
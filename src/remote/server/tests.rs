@@ -0,0 +1,118 @@
+use std::{env, fs, process};
+
+use tiny_http::{Method, Request, TestRequest};
+
+use super::{header, HttpResponse, Remote, Subscribers};
+use crate::{
+    config::{read_config, Config},
+    dmx::universe::OutputConfig,
+    remote::RemoteConfig,
+};
+
+const ORIGIN: &str = "http://192.168.1.20:8000";
+const TOKEN: &str = "s3cret-token";
+
+fn remote(token: Option<&str>, config: Config) -> Remote {
+    Remote {
+        remote_config: RemoteConfig {
+            listen: ([127, 0, 0, 1], 0).into(),
+            allowed_origins: vec![ORIGIN.to_string()],
+            token: token.map(str::to_string),
+        },
+        config,
+        from_frontend: crossbeam_channel::unbounded().0,
+        audio_control: crossbeam_channel::unbounded().0,
+        dmx_control: crossbeam_channel::unbounded().0,
+        subscribers: Subscribers::default(),
+    }
+}
+
+fn request(method: Method, path: &str, headers: &[(&str, &str)]) -> Request {
+    headers
+        .iter()
+        .fold(
+            TestRequest::new().with_method(method).with_path(path),
+            |request, (field, value)| request.with_header(header(field, value)),
+        )
+        .into()
+}
+
+fn get(path: &str, headers: &[(&str, &str)]) -> Request {
+    request(Method::Get, path, headers)
+}
+
+fn status(response: HttpResponse) -> u16 {
+    response.status_code().0
+}
+
+#[test]
+fn only_allowed_origins_are_accepted() {
+    let remote = remote(None, Config::default());
+
+    assert_eq!(
+        remote
+            .check_origin(&get("/api/scenes", &[("Origin", ORIGIN)]))
+            .ok(),
+        Some(Some(ORIGIN.to_string()))
+    );
+
+    // Other clients than browsers send no origin.
+    assert_eq!(
+        remote.check_origin(&get("/api/scenes", &[])).ok(),
+        Some(None)
+    );
+
+    for origin in ["http://evil.example", "http://192.168.1.20:8001", "null"] {
+        let response = remote
+            .check_origin(&get("/api/scenes", &[("Origin", origin)]))
+            .unwrap_err();
+        assert_eq!(status(response), 403, "{origin}");
+    }
+}
+
+#[test]
+fn the_token_is_required_if_configured() {
+    let protected = remote(Some(TOKEN), Config::default());
+    let bearer = format!("Bearer {TOKEN}");
+
+    for request in [
+        get("/api/scenes", &[("Authorization", &bearer)]),
+        get(&format!("/api/stream?token={TOKEN}"), &[]),
+        get(&format!("/api/stream?other=1&token={TOKEN}"), &[]),
+        // A CORS preflight cannot carry it.
+        request(Method::Options, "/api/master", &[("Origin", ORIGIN)]),
+    ] {
+        assert!(protected.check_token(&request).is_ok(), "{}", request.url());
+    }
+
+    for request in [
+        get("/api/scenes", &[]),
+        get("/api/scenes", &[("Authorization", "Bearer wrong")]),
+        get("/api/scenes", &[("Authorization", TOKEN)]),
+        get("/api/stream?token=wrong", &[]),
+        get(&format!("/api/stream?token={TOKEN}x"), &[]),
+        get(&format!("/api/stream?xtoken={TOKEN}"), &[]),
+    ] {
+        let response = protected.check_token(&request).unwrap_err();
+        assert_eq!(status(response), 401, "{}", request.url());
+    }
+
+    // Without a token every request is let through.
+    let open = remote(None, Config::default());
+    assert!(open.check_token(&get("/api/scenes", &[])).is_ok());
+}
+
+#[test]
+fn the_patch_has_the_current_outputs() {
+    let dir = env::temp_dir().join(format!("blaulicht-remote-{}", process::id()));
+    let config = read_config(dir.join("config.toml")).unwrap();
+    let remote = remote(None, config.clone());
+
+    config.store_output(1, &OutputConfig::Dummy).unwrap();
+    let response = remote.handle(&mut get("/api/patch", &[])).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let body = response.into_reader().into_inner();
+    let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(patch["universes"][0]["output"]["type"], "dummy");
+}
@@ -0,0 +1,198 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::SystemMessage;
+
+/// The first restart is delayed by this, every further one twice as long.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A worker which ran this long before it failed starts over with the initial delay.
+const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+/// How often a stop is noticed while a restart is pending.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// The threads of an instance. The audio, analysis and DMX workers are supervised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Worker {
+    /// Picks the audio input and starts the analysis.
+    Audio,
+    /// Analyzes the input and runs the effect engine.
+    Analysis,
+    /// Transmits the universes.
+    Dmx,
+    Osc,
+    Remote,
+}
+
+impl fmt::Display for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Worker::Audio => "audio",
+            Worker::Analysis => "analysis",
+            Worker::Dmx => "DMX",
+            Worker::Osc => "OSC",
+            Worker::Remote => "remote",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Reported as `SystemMessage::Worker` whenever it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum WorkerState {
+    Running,
+    /// The worker returned an error or panicked, it is restarted after `retry_in`.
+    Failed {
+        error: String,
+        restarts: u32,
+        retry_in: Duration,
+    },
+    /// The worker finished or was asked to stop.
+    Stopped,
+}
+
+/// An error a worker keeps running after, e.g. an output which could not be opened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerError {
+    pub worker: Worker,
+    pub message: String,
+}
+
+impl WorkerError {
+    pub fn new(worker: Worker, message: impl Into<String>) -> Self {
+        Self {
+            worker,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.worker, self.message)
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+fn report(system_out: &Sender<SystemMessage>, worker: Worker, state: WorkerState) {
+    system_out.send(SystemMessage::Worker(worker, state)).ok();
+}
+
+/// Runs `work` until it returns `Ok`. It is restarted with a growing delay when it returns an
+/// error or panics, unless `stopped` says that it was asked to stop in the meantime.
+pub fn supervise(
+    worker: Worker,
+    system_out: &Sender<SystemMessage>,
+    stopped: impl Fn() -> bool,
+    mut work: impl FnMut() -> Result<()>,
+) {
+    let mut restarts = 0;
+    let mut backoff = INITIAL_BACKOFF;
+
+    'restart: loop {
+        report(system_out, worker, WorkerState::Running);
+        let started = Instant::now();
+
+        let error = match panic::catch_unwind(AssertUnwindSafe(&mut work)) {
+            Ok(Ok(())) => break,
+            Ok(Err(err)) => format!("{err:#}"),
+            Err(payload) => format!("panicked: {}", panic_message(&*payload)),
+        };
+        if stopped() {
+            break;
+        }
+
+        if started.elapsed() >= HEALTHY_AFTER {
+            backoff = INITIAL_BACKOFF;
+        }
+        restarts += 1;
+        error!("[{worker}] Failed, restarting in {backoff:?}: {error}");
+        report(
+            system_out,
+            worker,
+            WorkerState::Failed {
+                error,
+                restarts,
+                retry_in: backoff,
+            },
+        );
+
+        let restart_at = Instant::now() + backoff;
+        while Instant::now() < restart_at {
+            if stopped() {
+                break 'restart;
+            }
+            thread::sleep(STOP_POLL);
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    info!("[{worker}] Stopped");
+    report(system_out, worker, WorkerState::Stopped);
+}
+
+/// Owns the supervised workers of an instance.
+pub struct Supervisor {
+    system_out: Sender<SystemMessage>,
+    threads: Vec<(Worker, JoinHandle<()>)>,
+}
+
+impl Supervisor {
+    pub fn new(system_out: Sender<SystemMessage>) -> Self {
+        Self {
+            system_out,
+            threads: vec![],
+        }
+    }
+
+    /// Runs `work` on its own thread, see [`supervise`]. It is called again for every restart,
+    /// so it has to set up its state from scratch.
+    pub fn spawn(
+        &mut self,
+        worker: Worker,
+        stopped: impl Fn() -> bool + Send + 'static,
+        work: impl FnMut() -> Result<()> + Send + 'static,
+    ) -> Result<()> {
+        let system_out = self.system_out.clone();
+        let thread = thread::Builder::new()
+            .name(worker.to_string())
+            .spawn(move || supervise(worker, &system_out, stopped, work))
+            .with_context(|| format!("Failed to start the {worker} worker"))?;
+
+        self.threads.push((worker, thread));
+        Ok(())
+    }
+
+    /// Waits until a worker has stopped, it has to be asked to stop first.
+    pub fn join(&mut self, worker: Worker) {
+        let (joined, running): (Vec<_>, Vec<_>) =
+            self.threads.drain(..).partition(|(w, _)| *w == worker);
+        self.threads = running;
+
+        for (_, thread) in joined {
+            // Panics are caught by `supervise`.
+            thread.join().ok();
+        }
+    }
+}
@@ -5,6 +5,7 @@ use cpal::{Device, HostId};
 use log::LevelFilter;
 use std::io::{Read, Write};
 use std::process::exit;
+use std::time::Duration;
 
 pub fn init_logger() {
     simple_logger::SimpleLogger::new()
@@ -15,12 +16,20 @@ pub fn init_logger() {
         .unwrap();
 }
 
+/// Weight of a new value in an exponential moving average, independent of the update rate.
+pub fn smoothing_factor(elapsed: Duration, time_constant: Duration) -> f32 {
+    if time_constant.is_zero() {
+        return 1.0;
+    }
+    1.0 - (-elapsed.as_secs_f32() / time_constant.as_secs_f32()).exp()
+}
+
 pub fn device_from_name(dev_id: String) -> Option<Device> {
     let devices = get_input_devices_flat();
 
     let selected_device: Option<(HostId, Device)> = devices
         .into_iter()
-        .find(|(this_host, this_dev)| this_dev.name().unwrap() == dev_id);
+        .find(|(this_host, this_dev)| this_dev.name().is_ok_and(|name| name == dev_id));
 
     let Some((_, device)) = selected_device else {
         return None;
@@ -34,7 +43,7 @@ pub fn device_from_names(host_id: String, dev_id: String) -> Option<Device> {
 
     let selected_device: Option<(HostId, Device)> =
         devices.into_iter().find(|(this_host, this_dev)| {
-            this_host.name() == host_id && this_dev.name().unwrap() == dev_id
+            this_host.name() == host_id && this_dev.name().is_ok_and(|name| name == dev_id)
         });
 
     let Some((_, device)) = selected_device else {
@@ -44,17 +53,24 @@ pub fn device_from_names(host_id: String, dev_id: String) -> Option<Device> {
     Some(device)
 }
 
+/// The host of a device, devices only know their name.
+pub fn host_of(device: &Device) -> Option<HostId> {
+    let name = device.name().ok()?;
+    get_input_devices_flat()
+        .into_iter()
+        .find(|(_, this_dev)| this_dev.name().is_ok_and(|this_name| this_name == name))
+        .map(|(host, _)| host)
+}
+
 /// Returns all valid and available input devices.
 fn get_input_devices() -> Vec<(cpal::HostId, Vec<cpal::Device>)> {
     cpal::available_hosts()
         .into_iter()
-        .map(|host_id| {
-            let host = cpal::host_from_id(host_id).expect("should know the just queried host");
-            (host_id, host)
+        // Hosts which cannot be opened, e.g. a stopped sound server, have no devices.
+        .filter_map(|host_id| {
+            let devices = cpal::host_from_id(host_id).ok()?.devices().ok()?;
+            Some((host_id, devices))
         })
-        .map(|(host_id, host)| (host_id, host.devices()))
-        .filter(|(_, devices)| devices.is_ok())
-        .map(|(host_id, devices)| (host_id, devices.unwrap()))
         .map(|(host_id, devices)| {
             (
                 host_id,